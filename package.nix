@@ -1,4 +1,4 @@
-{ lib, rustPlatform, pkg-config, openssl, path, makeWrapper, nix }:
+{ lib, stdenv, rustPlatform, pkg-config, openssl, path, makeWrapper, nix, gnutar, unzip, patch, bubblewrap }:
 
 rustPlatform.buildRustPackage {
   name = "nixpkgs-check";
@@ -20,6 +20,6 @@ rustPlatform.buildRustPackage {
 
   postInstall = ''
     wrapProgram "$out/bin/nixpkgs-check" \
-      --prefix PATH : "${lib.makeBinPath [ nix ]}"
+      --prefix PATH : "${lib.makeBinPath ([ nix gnutar unzip patch ] ++ lib.optional stdenv.isLinux bubblewrap)}"
   '';
 }
@@ -0,0 +1,115 @@
+//! Recovery of the logs of failed builds, for inclusion in the report
+
+use anyhow::{anyhow, Context};
+use crossbeam_channel::Receiver;
+use std::path::{Path, PathBuf};
+
+/// Number of lines of the log included in the report
+const EXCERPT_LINES: usize = 30;
+
+pub struct FailureLog {
+    drv: Option<String>,
+    excerpt: Vec<String>,
+    saved_to: PathBuf,
+}
+
+impl FailureLog {
+    /// Finds the derivation whose builder failed in the stderr of nix,
+    /// and saves its log to `run_dir`
+    pub fn recover(
+        killer: &Receiver<()>,
+        nix_stderr: &str,
+        run_dir: &Path,
+    ) -> anyhow::Result<Option<FailureLog>> {
+        let drv = failed_drvs(nix_stderr).into_iter().next();
+        FailureLog::recover_drv(killer, drv, nix_stderr, run_dir)
+    }
+
+    /// Saves the log of `drv` to `run_dir`, falling back to the stderr of
+    /// nix if the derivation is unknown or has no log
+    pub fn recover_drv(
+        killer: &Receiver<()>,
+        drv: Option<String>,
+        nix_stderr: &str,
+        run_dir: &Path,
+    ) -> anyhow::Result<Option<FailureLog>> {
+        let log = match &drv {
+            Some(drv) => match crate::run_nix(killer, true, &["log", drv])
+                .with_context(|| format!("recovering the build log of {}", drv))?
+            {
+                Some(out) => String::from_utf8_lossy(&out.stdout).into_owned(),
+                None => return Ok(None),
+            },
+            None => String::new(),
+        };
+
+        // nix does not keep logs of all failed builds, in which case the
+        // tail it printed is the best we have
+        let log = if log.trim().is_empty() {
+            nix_stderr.to_string()
+        } else {
+            log
+        };
+
+        let name = match &drv {
+            Some(drv) => Path::new(drv)
+                .file_name()
+                .ok_or_else(|| anyhow!("derivation {} has no file name", drv))?
+                .to_string_lossy()
+                .into_owned(),
+            None => format!("{}.drv", uuid::Uuid::new_v4()),
+        };
+        let saved_to = run_dir.join(name + ".log");
+        std::fs::create_dir_all(run_dir)
+            .with_context(|| format!("creating the run directory {:?}", run_dir))?;
+        std::fs::write(&saved_to, &log)
+            .with_context(|| format!("saving the build log to {:?}", saved_to))?;
+
+        let lines = log.lines().collect::<Vec<_>>();
+        let excerpt = lines[lines.len().saturating_sub(EXCERPT_LINES)..]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        Ok(Some(FailureLog {
+            drv,
+            excerpt,
+            saved_to,
+        }))
+    }
+
+    /// Returns a collapsible block with the end of the log
+    pub fn report(&self) -> String {
+        let mut res = format!(
+            "\n<details><summary>build log of <code>{}</code></summary>\n\n```\n",
+            self.drv.as_deref().unwrap_or("an unknown derivation"),
+        );
+        for l in &self.excerpt {
+            res += l;
+            res += "\n";
+        }
+        res += "```\n";
+        res += &format!("\nThe full log is in `{}`\n", self.saved_to.display());
+        res += "\n</details>\n";
+        res
+    }
+}
+
+/// Returns the derivations whose builder failed, according to the
+/// stderr of nix, in the order they failed
+pub fn failed_drvs(nix_stderr: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    for line in nix_stderr.lines() {
+        for prefix in &["builder for '", "Cannot build '"] {
+            if let Some(start) = line.find(prefix) {
+                let rest = &line[start + prefix.len()..];
+                if let Some(end) = rest.find('\'') {
+                    let drv = rest[..end].to_string();
+                    if !res.contains(&drv) {
+                        res.push(drv);
+                    }
+                }
+            }
+        }
+    }
+    res
+}
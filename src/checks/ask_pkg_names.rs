@@ -1,13 +1,21 @@
 use anyhow::Context;
 use crossbeam_channel::Receiver;
-use std::collections::HashSet;
+use std::{collections::HashSet, rc::Rc};
 
 pub struct Chk {
     pkgs: HashSet<String>,
+    options: Rc<crate::Options>,
+    commits: Rc<Vec<crate::Commit>>,
+    github_handle: Option<String>,
 }
 
 impl Chk {
-    pub fn new(pkgs: HashSet<String>) -> anyhow::Result<Chk> {
+    pub fn new(
+        pkgs: HashSet<String>,
+        options: Rc<crate::Options>,
+        commits: Rc<Vec<crate::Commit>>,
+        state: &mut crate::State,
+    ) -> anyhow::Result<Chk> {
         let choices = pkgs.into_iter().collect::<Vec<String>>();
 
         let mut pkgs = HashSet::new();
@@ -39,7 +47,22 @@ impl Chk {
             }
             pkgs.insert(pkg);
         }
-        Ok(Chk { pkgs })
+
+        if state.github_handle.is_none() {
+            let handle: String = dialoguer::Input::with_theme(&*crate::theme())
+                .allow_empty(true)
+                .with_prompt("what is your GitHub handle, not to mention you in the report? [empty to not set it]")
+                .interact_text()
+                .context("asking the user for their GitHub handle")?;
+            state.github_handle = Some(handle.trim().trim_start_matches('@').to_string());
+        }
+        let github_handle = state.github_handle.clone().filter(|h| !h.is_empty());
+        Ok(Chk {
+            pkgs,
+            options,
+            commits,
+            github_handle,
+        })
     }
 }
 
@@ -61,12 +84,27 @@ impl crate::Check for Chk {
     }
 
     fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
-        self.pkgs
-            .iter()
-            .map(|pkg| {
-                Ok(Box::new(crate::checks::build::Chk::new(pkg.clone())?) as Box<dyn crate::Check>)
-            })
-            .collect()
+        let mut res = Vec::new();
+        // The tests of all the packages are built together
+        let tests = Rc::new(crate::checks::run_tests::Batch::default());
+        for pkg in &self.pkgs {
+            res.push(Box::new(crate::checks::build::Chk::new(
+                pkg.clone(),
+                self.options.clone(),
+                self.commits.clone(),
+                tests.clone(),
+            )?) as Box<dyn crate::Check>);
+        }
+        res.push(Box::new(crate::checks::commit_messages::Chk::new(
+            self.commits.clone(),
+        )));
+        if !self.pkgs.is_empty() {
+            res.push(Box::new(crate::checks::mentions::Chk::new(
+                self.pkgs.clone(),
+                self.github_handle.clone(),
+            )));
+        }
+        Ok(res)
     }
 
     fn report(&self) -> String {
@@ -1,26 +1,104 @@
 use anyhow::{anyhow, Context};
 use crossbeam_channel::Receiver;
-use std::{path::Path, rc::Rc};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How an output of a build came to be in the store
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Provenance {
+    Built,
+    Substituted,
+    AlreadyPresent,
+}
+
+struct BuildStats {
+    wall_time: Duration,
+    /// Maps output names to their provenance
+    outputs: BTreeMap<String, Provenance>,
+}
+
+impl BuildStats {
+    fn built_locally(&self) -> bool {
+        !self.outputs.is_empty() && self.outputs.values().all(|p| *p == Provenance::Built)
+    }
+
+    fn describe(&self) -> String {
+        let mut by_provenance = BTreeMap::<Provenance, Vec<&str>>::new();
+        for (output, provenance) in &self.outputs {
+            by_provenance.entry(*provenance).or_default().push(output);
+        }
+        by_provenance
+            .into_iter()
+            .map(|(provenance, outputs)| {
+                format!(
+                    "{} ({})",
+                    match provenance {
+                        Provenance::Built => "built locally",
+                        Provenance::Substituted => "substituted from a cache",
+                        Provenance::AlreadyPresent => "already in the store",
+                    },
+                    outputs.join(", "),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
 
 pub struct Chk {
     pkg: String,
+    exists_before: Option<bool>,
+    exists_after: Option<bool>,
     builds_before: Option<bool>,
     builds_after: Option<bool>,
+    stats_before: Option<BuildStats>,
+    stats_after: Option<BuildStats>,
+    failure_log_after: Option<crate::build_log::FailureLog>,
     outs_dir: Rc<tempfile::TempDir>,
+    options: Rc<crate::Options>,
+    commits: Rc<Vec<crate::Commit>>,
+    tests: Rc<crate::checks::run_tests::Batch>,
+    sources: Rc<crate::sources::Shared>,
 }
 
 impl Chk {
-    pub fn new(pkg: String) -> anyhow::Result<Chk> {
+    pub fn new(
+        pkg: String,
+        options: Rc<crate::Options>,
+        commits: Rc<Vec<crate::Commit>>,
+        tests: Rc<crate::checks::run_tests::Batch>,
+    ) -> anyhow::Result<Chk> {
         Ok(Chk {
+            sources: Rc::new(crate::sources::Shared::new(pkg.clone())),
             pkg,
+            exists_before: None,
+            exists_after: None,
             builds_before: None,
             builds_after: None,
+            stats_before: None,
+            stats_after: None,
+            failure_log_after: None,
             outs_dir: Rc::new(
                 tempfile::tempdir()
                     .context("creating temporary directory to hold build results")?,
             ),
+            options,
+            commits,
+            tests,
         })
     }
+
+    fn is_new(&self) -> bool {
+        self.exists_before == Some(false) && self.exists_after == Some(true)
+    }
+
+    fn is_removed(&self) -> bool {
+        self.exists_before == Some(true) && self.exists_after == Some(false)
+    }
 }
 
 impl crate::Check for Chk {
@@ -36,37 +114,167 @@ impl crate::Check for Chk {
     }
 
     fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
-        self.builds_before = build(killer, self.outs_dir.path(), "base", &self.pkg)?;
+        self.exists_before = crate::attr_exists(killer, &self.pkg)?;
+        if self.exists_before != Some(true) {
+            return Ok(());
+        }
+        let res = build(
+            killer,
+            self.outs_dir.path(),
+            &self.options.run_dir,
+            "base",
+            &self.pkg,
+        )?;
+        self.builds_before = res.as_ref().map(|(success, _, _)| *success);
+        self.stats_before = res.map(|(_, stats, _)| stats);
         Ok(())
     }
 
     fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
-        self.builds_after = build(killer, self.outs_dir.path(), "to-check", &self.pkg)?;
+        self.exists_after = crate::attr_exists(killer, &self.pkg)?;
+        if self.exists_after != Some(true) {
+            return Ok(());
+        }
+        let res = build(
+            killer,
+            self.outs_dir.path(),
+            &self.options.run_dir,
+            "to-check",
+            &self.pkg,
+        )?;
+        self.builds_after = res.as_ref().map(|(success, _, _)| *success);
+        if let Some((_, stats, failure_log)) = res {
+            self.stats_after = Some(stats);
+            self.failure_log_after = failure_log;
+        }
         Ok(())
     }
 
     fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
-        let mut res = Vec::new();
+        let mut res = Vec::<Box<dyn crate::Check>>::new();
+        // These checks evaluate the package on both sides
+        if self.exists_before == Some(true) && self.exists_after == Some(true) {
+            res.push(Box::new(crate::checks::meta_lint::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
+            )));
+            res.push(Box::new(crate::checks::disabled_tests::Chk::new(
+                self.pkg.clone(),
+            )));
+            res.push(Box::new(crate::checks::changelog::Chk::new(
+                self.pkg.clone(),
+                self.sources.clone(),
+            )));
+            res.push(Box::new(crate::checks::source_diff::Chk::new(
+                self.pkg.clone(),
+                self.sources.clone(),
+            )));
+            res.push(Box::new(crate::checks::patches::Chk::new(
+                self.pkg.clone(),
+                self.sources.clone(),
+            )));
+            res.push(Box::new(crate::checks::dependencies::Chk::new(
+                self.pkg.clone(),
+                self.sources.clone(),
+            )));
+            res.push(Box::new(crate::checks::fod_hashes::Chk::new(
+                self.pkg.clone(),
+            )));
+            res.push(Box::new(crate::checks::licensing::Chk::new(
+                self.pkg.clone(),
+            )));
+        }
+        if self.is_removed() {
+            res.push(Box::new(crate::checks::removed_package::Chk::new(
+                self.pkg.clone(),
+            )));
+        }
+        if self.is_new() {
+            res.push(Box::new(crate::checks::new_package::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
+                self.commits.clone(),
+            )));
+        }
         if self.builds_before == Some(true) && self.builds_after == Some(true) {
-            res.push(
-                Box::new(crate::checks::closure_size::Chk::new(self.pkg.clone()))
-                    as Box<dyn crate::Check>,
-            );
+            res.push(Box::new(crate::checks::closure_size::Chk::new(
+                self.pkg.clone(),
+            )));
         }
         if self.builds_after == Some(true) {
             res.push(Box::new(crate::checks::run_tests::Chk::new(
                 self.pkg.clone(),
+                self.options.clone(),
+                self.tests.clone(),
             )));
             res.push(Box::new(crate::checks::run_binaries::Chk::new(
                 self.pkg.clone(),
                 self.outs_dir.clone(),
+                self.options.clone(),
+            )));
+            res.push(Box::new(crate::checks::linkage::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
             )));
+            res.push(Box::new(crate::checks::references::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
+            )));
+            res.push(Box::new(crate::checks::hygiene::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
+            )));
+            res.push(Box::new(crate::checks::hardening::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
+            )));
+            res.push(Box::new(crate::checks::main_program::Chk::new(
+                self.pkg.clone(),
+                self.outs_dir.clone(),
+            )));
+            if self.options.check_reproducibility {
+                res.push(Box::new(crate::checks::reproducibility::Chk::new(
+                    self.pkg.clone(),
+                    self.outs_dir.clone(),
+                )));
+            }
         }
         Ok(res)
     }
 
     fn report(&self) -> String {
-        match (self.builds_before, self.builds_after) {
+        if self.is_new() {
+            let res = match self.builds_after {
+                None => format!(
+                    "**package {}:** 😢 new package, build interrupted",
+                    self.pkg
+                ),
+                Some(true) => format!("**package {}:** ✔ new package, builds", self.pkg),
+                Some(false) => format!("**package {}:** ❌ new package, does not build", self.pkg),
+            };
+            return match &self.failure_log_after {
+                Some(log) => res + "\n" + &log.report(),
+                None => res,
+            };
+        }
+        if self.is_removed() {
+            return format!(
+                "**package {}:** ✔ removed, base build {}",
+                self.pkg,
+                match self.builds_before {
+                    None => "interrupted",
+                    Some(true) => "passed",
+                    Some(false) => "did not pass",
+                }
+            );
+        }
+        if self.exists_before == Some(false) && self.exists_after == Some(false) {
+            return format!(
+                "**package {}:** ❌ exists neither in base nor in to-check",
+                self.pkg
+            );
+        }
+        let res = match (self.builds_before, self.builds_after) {
             (None, None) => format!("**package {}:** 😢 both builds interrupted", self.pkg),
             (None, Some(after)) => format!(
                 "**package {}:** 😢 base build interrupted, to-check build {}",
@@ -86,20 +294,76 @@ impl crate::Check for Chk {
             (Some(false), Some(false)) => {
                 format!("**package {}:** 😢 still does not build", self.pkg)
             }
+        };
+        let mut res = res + &self.report_build_time();
+        if let Some(log) = &self.failure_log_after {
+            res += "\n";
+            res += &log.report();
+        }
+        res
+    }
+}
+
+impl Chk {
+    fn report_build_time(&self) -> String {
+        let (before, after) = match (&self.stats_before, &self.stats_after) {
+            (Some(before), Some(after)) if self.builds_after == Some(true) => (before, after),
+            _ => return String::new(),
+        };
+        let mut res = format!(
+            "\n  * *base build:* {}, in {}\n  * *to-check build:* {}, in {}\n  * *note:* times are wall-clock, the CPU time of builds run by the nix daemon is not measured",
+            if self.builds_before == Some(true) {
+                before.describe()
+            } else {
+                "failed".to_string()
+            },
+            format_duration(before.wall_time),
+            after.describe(),
+            format_duration(after.wall_time),
+        );
+        if self.builds_before != Some(true) {
+            return res;
+        }
+        if !before.built_locally() || !after.built_locally() {
+            res += "\n  * *build time:* 😢 not compared, as not all outputs were built locally";
+            return res;
         }
+        let ratio = after.wall_time.as_secs_f64() / before.wall_time.as_secs_f64();
+        let threshold = self.options.build_time_threshold;
+        res += &if ratio >= threshold {
+            format!("\n  * *build time:* 😢 {:.1}x slower", ratio)
+        } else if ratio <= 1. / threshold {
+            format!("\n  * *build time:* 💚 {:.1}x faster", 1. / ratio)
+        } else {
+            String::from("\n  * *build time:* ✔ roughly unchanged")
+        };
+        res
     }
 }
 
-/// Returns true iff the build was successful
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", d.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+/// Returns true iff the build was successful, along with statistics
+/// about the build and, if it failed, the log of the failing derivation
 fn build(
     killer: &Receiver<()>,
     outs_dir: &Path,
+    run_dir: &Path,
     version: &str,
     pkg: &str,
-) -> anyhow::Result<Option<bool>> {
-    Ok(crate::run_nix(
+) -> anyhow::Result<Option<(bool, BuildStats, Option<crate::build_log::FailureLog>)>> {
+    let start = Instant::now();
+    let (status, log) = match crate::run_nix_logged(
         killer,
-        false,
         &[
             "build",
             "--out-link",
@@ -111,5 +375,144 @@ fn build(
         ],
     )
     .with_context(|| format!("building the {} version of package {}", version, pkg))?
-    .map(|out| out.status.success()))
+    {
+        Some(res) => res,
+        None => return Ok(None),
+    };
+    let mut stats = BuildStats {
+        wall_time: start.elapsed(),
+        outputs: BTreeMap::new(),
+    };
+    if !status.success() {
+        let failure_log = crate::build_log::FailureLog::recover(killer, &log, run_dir)
+            .with_context(|| format!("recovering the {} build log of {}", version, pkg))?;
+        return Ok(Some((false, stats, failure_log)));
+    }
+
+    // Figure out where the outputs come from
+    let (built, fetched) = parse_build_log(&log);
+    for (output, link) in outputs(outs_dir, version)? {
+        let path = std::fs::canonicalize(&link)
+            .with_context(|| format!("resolving the store path of {:?}", link))?;
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("got non-utf8 store path {:?}", path))?;
+        let deriver = match crate::nix(killer, &["path-info", "--json", path])
+            .with_context(|| format!("recovering the deriver of {}", path))?
+        {
+            Some(info) => info
+                .get(0)
+                .or_else(|| info.get(path))
+                .and_then(|i| i.get("deriver"))
+                .and_then(|d| d.as_str())
+                .map(|d| d.to_string()),
+            None => return Ok(None),
+        };
+        let was_built = match &deriver {
+            Some(d) => built.contains(d),
+            None => false,
+        };
+        let provenance = if fetched.contains(path) {
+            Provenance::Substituted
+        } else if was_built {
+            Provenance::Built
+        } else {
+            Provenance::AlreadyPresent
+        };
+        stats.outputs.insert(output, provenance);
+    }
+    Ok(Some((true, stats, None)))
+}
+
+/// Returns the derivations that nix built and the paths that it
+/// fetched, according to its log
+fn parse_build_log(log: &str) -> (HashSet<String>, HashSet<String>) {
+    let mut built = HashSet::new();
+    let mut fetched = HashSet::new();
+    let mut listing = None;
+    for line in log.lines() {
+        let trimmed = line.trim();
+
+        // Lists of the form "these derivations will be built:" followed
+        // by indented paths
+        if line.starts_with(char::is_whitespace) && trimmed.starts_with("/nix/store/") {
+            match listing {
+                Some(true) => built.insert(trimmed.to_string()),
+                Some(false) => fetched.insert(trimmed.to_string()),
+                None => false,
+            };
+            continue;
+        }
+        listing = if line.contains("will be built") {
+            Some(true)
+        } else if line.contains("will be fetched") {
+            Some(false)
+        } else {
+            None
+        };
+
+        // Progress lines of the form "building '...'" and "copying path
+        // '...' from '...'"
+        let quoted = |prefix: &str| -> Option<(String, String)> {
+            let rest = trimmed.strip_prefix(prefix)?;
+            let end = rest.find('\'')?;
+            Some((rest[..end].to_string(), rest[end + 1..].to_string()))
+        };
+        if let Some((drv, _)) = quoted("building '") {
+            built.insert(drv);
+        } else if let Some((path, rest)) = quoted("copying path '") {
+            if rest.starts_with(" from ") {
+                fetched.insert(path);
+            }
+        }
+    }
+    (built, fetched)
+}
+
+/// Lists the outputs built for `version`, as (output name, out-link)
+/// pairs
+pub fn outputs(outs_dir: &Path, version: &str) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut res = Vec::new();
+    let entries = match std::fs::read_dir(outs_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(res),
+        Err(e) => {
+            return Err(e).with_context(|| format!("listing build results in {:?}", outs_dir))
+        }
+    };
+    for entry in entries {
+        let entry = entry.with_context(|| format!("listing build results in {:?}", outs_dir))?;
+        let name = entry.file_name();
+        let name = name
+            .to_str()
+            .ok_or_else(|| anyhow!("got non-utf8 out-link name {:?}", name))?;
+        if name == version {
+            res.push((String::from("out"), entry.path()));
+        } else if let Some(output) = name.strip_prefix(&format!("{}-", version)) {
+            res.push((output.to_string(), entry.path()));
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+/// Lists everything inside the outputs built for `version`, as
+/// (`$output/relative/path`, store path) pairs
+pub fn output_files(outs_dir: &Path, version: &str) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut res = Vec::new();
+    for (output, link) in outputs(outs_dir, version)? {
+        let root = std::fs::canonicalize(&link)
+            .with_context(|| format!("resolving the store path of {:?}", link))?;
+        if !root.is_dir() {
+            res.push((format!("${}", output), root));
+            continue;
+        }
+        for f in crate::walk_dir(&root)? {
+            let rel = f
+                .strip_prefix(&root)
+                .expect("walked file is not in walked directory");
+            res.push((format!("${}/{}", output, rel.display()), f));
+        }
+    }
+    Ok(res)
 }
@@ -0,0 +1,194 @@
+use crossbeam_channel::Receiver;
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Maximum number of lines of the changelog put in the report
+const MAX_LINES: usize = 200;
+
+/// Prefixes of the (uppercased) names of the files that could be a changelog
+const CHANGELOG_NAMES: &[&str] = &["CHANGELOG", "CHANGES", "NEWS", "RELEASE", "HISTORY"];
+
+pub struct Chk {
+    pkg: String,
+    sources: Rc<crate::sources::Shared>,
+    before: Option<Rc<crate::sources::Source>>,
+    after: Option<Rc<crate::sources::Source>>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, sources: Rc<crate::sources::Shared>) -> Chk {
+        Chk {
+            pkg,
+            sources,
+            before: None,
+            after: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x78ef20b118d74c80a124e48f5ef97695),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("changelog({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.before = self.sources.get(killer, "base")?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.after = self.sources.get(killer, "to-check")?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.before, &self.after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => return format!("**version of {}:** 😢 check was interrupted", self.pkg),
+        };
+        let (version_before, version_after) = match (&before.version, &after.version) {
+            (Some(b), Some(a)) if a != b => (b, a),
+            (_, Some(a)) => return format!("**version of {}:** ✔ {}, unchanged", self.pkg, a),
+            (_, None) => return format!("**version of {}:** ✔ no version", self.pkg),
+        };
+
+        let mut res = format!(
+            "**version of {}:** {} → {}",
+            self.pkg, version_before, version_after
+        );
+        let tree_after = match &after.tree {
+            Some(tree) => tree,
+            None => return res + "\n  * 😢 the source could not be unpacked to find a changelog",
+        };
+        let changelog = match find_changelog(tree_after) {
+            Some(changelog) => changelog,
+            None => return res + "\n  * 😢 no changelog found in the source",
+        };
+        let name = changelog
+            .strip_prefix(tree_after)
+            .expect("changelog is not in the source tree")
+            .display()
+            .to_string();
+        let text = match std::fs::read(&changelog) {
+            Ok(text) => String::from_utf8_lossy(&text).into_owned(),
+            Err(e) => return res + &format!("\n  * 😢 failed reading `{}`: {}", name, e),
+        };
+        let old_text = before
+            .tree
+            .as_ref()
+            .and_then(|tree| std::fs::read(tree.join(&name)).ok())
+            .map(|text| String::from_utf8_lossy(&text).into_owned());
+
+        let section = match section(&text, old_text.as_deref(), version_before, version_after) {
+            Some(section) => section,
+            None => {
+                return res
+                    + &format!(
+                        "\n  * 😢 found no entry for {} in `{}`",
+                        version_after, name
+                    )
+            }
+        };
+        res += &format!(
+            "\n\n<details><summary>changes from <code>{}</code></summary>\n\n```\n",
+            name
+        );
+        for l in section.iter().take(MAX_LINES) {
+            res += l;
+            res += "\n";
+        }
+        if section.len() > MAX_LINES {
+            res += &format!("[… {} more lines]\n", section.len() - MAX_LINES);
+        }
+        res += "```\n\n</details>\n";
+        res
+    }
+}
+
+/// Returns the changelog of the source tree, looking at the root and
+/// at the documentation directories
+fn find_changelog(tree: &Path) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    for dir in &["", "doc", "docs"] {
+        let entries = match std::fs::read_dir(tree.join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_uppercase();
+            if entry.path().is_file() && CHANGELOG_NAMES.iter().any(|n| name.starts_with(n)) {
+                candidates.push(entry.path());
+            }
+        }
+        if !candidates.is_empty() {
+            break;
+        }
+    }
+    // Prefer the changelog proper over eg. release notes
+    candidates.sort_by_key(|c| {
+        let name = c.file_name().unwrap().to_string_lossy().to_uppercase();
+        CHANGELOG_NAMES.iter().position(|n| name.starts_with(n))
+    });
+    candidates.into_iter().next()
+}
+
+/// Returns the lines of the changelog that describe the changes between
+/// the two versions
+///
+/// This is the part between the first line mentioning the new version
+/// and the first following line mentioning the old version. If the new
+/// version is not mentioned, this falls back to the lines added at the
+/// top of the changelog since the old version.
+fn section<'a>(
+    text: &'a str,
+    old_text: Option<&str>,
+    version_before: &str,
+    version_after: &str,
+) -> Option<Vec<&'a str>> {
+    let lines = text.lines().collect::<Vec<_>>();
+    if let Some(start) = lines.iter().position(|l| mentions(l, version_after)) {
+        let end = lines[start + 1..]
+            .iter()
+            .position(|l| mentions(l, version_before))
+            .map(|i| start + 1 + i)
+            .unwrap_or_else(|| lines.len());
+        return Some(lines[start..end].to_vec());
+    }
+
+    let first_old_line = old_text?.lines().find(|l| !l.trim().is_empty())?;
+    let end = lines.iter().position(|l| *l == first_old_line)?;
+    if lines[..end].iter().all(|l| l.trim().is_empty()) {
+        return None;
+    }
+    Some(lines[..end].to_vec())
+}
+
+/// Returns true iff the line mentions the version, not as part of a
+/// longer version number
+fn mentions(line: &str, version: &str) -> bool {
+    line.match_indices(version).any(|(i, _)| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + version.len()..].chars().collect::<Vec<_>>();
+        let extends_before = matches!(before, Some(c) if c.is_ascii_alphanumeric() || c == '.');
+        let extends_after = match after[..] {
+            [c, ..] if c.is_ascii_alphanumeric() => true,
+            ['.', c, ..] if c.is_ascii_digit() => true,
+            _ => false,
+        };
+        // Versions are often prefixed with a v
+        (!extends_before || before == Some('v')) && !extends_after
+    })
+}
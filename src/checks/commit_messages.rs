@@ -0,0 +1,256 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{
+    collections::{BTreeSet, HashMap},
+    rc::Rc,
+};
+
+/// The longest summary that does not get truncated in the usual views
+const MAX_SUMMARY_LENGTH: usize = 72;
+
+/// Prefixes git gives to commits meant to be squashed into another one
+const FIXUP_PREFIXES: &[&str] = &["fixup!", "squash!", "amend!"];
+
+/// The version each package has, or `None` if it has none
+type Versions = HashMap<String, Option<String>>;
+
+pub struct Chk {
+    commits: Rc<Vec<crate::Commit>>,
+    versions_before: Option<Versions>,
+    versions_after: Option<Versions>,
+}
+
+impl Chk {
+    pub fn new(commits: Rc<Vec<crate::Commit>>) -> Chk {
+        Chk {
+            commits,
+            versions_before: None,
+            versions_after: None,
+        }
+    }
+
+    /// The packages whose versions the commit summaries claim to change
+    fn bumped_pkgs(&self) -> BTreeSet<String> {
+        self.commits
+            .iter()
+            .filter(|c| !c.is_merge)
+            .filter_map(|c| bump(&c.summary))
+            .map(|(pkg, _, _)| pkg.to_string())
+            .collect()
+    }
+
+    /// Returns the problems with the commit, with their emoji
+    fn problems(&self, commit: &crate::Commit) -> Vec<String> {
+        let summary = &commit.summary;
+        if commit.is_merge {
+            return vec!["❌ is a merge commit, please rebase instead".to_string()];
+        }
+        let mut res = Vec::new();
+        if FIXUP_PREFIXES.iter().any(|p| summary.starts_with(p)) {
+            res.push("❌ is meant to be squashed into another commit".to_string());
+        }
+        if summary
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|w| w.eq_ignore_ascii_case("wip"))
+        {
+            res.push("❌ is marked as work in progress".to_string());
+        }
+        if parse(summary).is_none() {
+            res.push(
+                "😢 does not follow the `pkg: 1.0 -> 1.1`, `pkg: init at 1.0` or `nixos/module: …` convention"
+                    .to_string(),
+            );
+        }
+        let length = summary.chars().count();
+        if length > MAX_SUMMARY_LENGTH {
+            res.push(format!(
+                "😢 has a {}-character summary, longer than {}",
+                length, MAX_SUMMARY_LENGTH
+            ));
+        }
+        if commit
+            .message
+            .lines()
+            .nth(1)
+            .map(|l| !l.trim().is_empty())
+            .unwrap_or(false)
+        {
+            res.push(
+                "😢 does not separate the summary from the body with a blank line".to_string(),
+            );
+        }
+        res.extend(self.version_problem(commit));
+        res
+    }
+
+    /// Checks a `pkg: a -> b` summary against the evaluated versions
+    ///
+    /// Commits bumping the same package several times are chained: only
+    /// the first one needs to start from the base version, and only the
+    /// last one needs to reach the to-check version.
+    fn version_problem(&self, commit: &crate::Commit) -> Option<String> {
+        let (pkg, from, to) = bump(&commit.summary)?;
+        let before = self.versions_before.as_ref()?.get(pkg)?.as_deref();
+        let after = self.versions_after.as_ref()?.get(pkg)?.as_deref();
+        let other_bumps = self
+            .commits
+            .iter()
+            .filter(|c| !c.is_merge && c.id != commit.id)
+            .filter_map(|c| bump(&c.summary))
+            .filter(|(p, _, _)| *p == pkg)
+            .collect::<Vec<_>>();
+        let continues = other_bumps.iter().any(|(_, _, t)| same_version(t, from));
+        let is_continued = other_bumps.iter().any(|(_, f, _)| same_version(f, to));
+        if !continues && !before.map(|b| same_version(b, from)).unwrap_or(false) {
+            return Some(format!(
+                "❌ starts from version {}, but the base version is {}",
+                from,
+                before.unwrap_or("unknown")
+            ));
+        }
+        if !is_continued && !after.map(|a| same_version(a, to)).unwrap_or(false) {
+            return Some(format!(
+                "❌ goes to version {}, but the to-check version is {}",
+                to,
+                after.unwrap_or("unknown")
+            ));
+        }
+        None
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid(uuid::Uuid::from_u128(0xd7ef2162a96a4711809e14d5b8430f37))
+    }
+
+    fn name(&self) -> String {
+        "commit-messages".to_string()
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.versions_before = versions(killer, "base", &self.bumped_pkgs())?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.versions_after = versions(killer, "to-check", &self.bumped_pkgs())?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        if self.versions_before.is_none() || self.versions_after.is_none() {
+            return "**commit messages:** 😢 check was interrupted".to_string();
+        }
+        let mut lines = Vec::new();
+        for c in self.commits.iter().rev() {
+            for p in self.problems(c) {
+                lines.push(format!(
+                    "`{}` {}: `{}`",
+                    &c.id[..c.id.len().min(12)],
+                    p,
+                    c.summary
+                ));
+            }
+        }
+        if lines.is_empty() {
+            return format!(
+                "**commit messages:** ✔ the {} commits follow the conventions",
+                self.commits.len()
+            );
+        }
+        let mut res = "**commit messages:**\n".to_string();
+        for l in lines {
+            res += &format!("  * {}\n", l);
+        }
+        res
+    }
+}
+
+/// Splits a conventional summary into what it changes and how, eg.
+/// `foo, bar` and `1.0 -> 1.1` for `foo, bar: 1.0 -> 1.1`
+fn parse(summary: &str) -> Option<(&str, &str)> {
+    let (attrs, rest) = summary.split_once(": ")?;
+    let is_attr = |a: &str| {
+        !a.is_empty()
+            && a.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.+'/-".contains(c))
+    };
+    if !attrs.split(", ").all(is_attr) || rest.trim().is_empty() {
+        return None;
+    }
+    Some((attrs, rest))
+}
+
+/// Returns the package, old and new versions of a `pkg: a -> b` summary
+fn bump(summary: &str) -> Option<(&str, &str, &str)> {
+    let (pkg, rest) = parse(summary)?;
+    let is_segment = |s: &str| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_'-".contains(c))
+    };
+    if !pkg.split('.').all(is_segment) {
+        return None;
+    }
+    let (from, to) = rest.split_once(" -> ").or_else(|| rest.split_once(" → "))?;
+    // Allow a trailing note, eg. `foo: 1.0 -> 1.1 (security)`
+    let to = to.split(' ').next().unwrap_or(to);
+    if from.is_empty() || to.is_empty() || from.contains(' ') {
+        return None;
+    }
+    Some((pkg, from, to))
+}
+
+/// Compares versions, ignoring the `v` prefix some summaries use
+fn same_version(a: &str, b: &str) -> bool {
+    a.trim_start_matches('v') == b.trim_start_matches('v')
+}
+
+fn versions(
+    killer: &Receiver<()>,
+    version: &str,
+    pkgs: &BTreeSet<String>,
+) -> anyhow::Result<Option<Versions>> {
+    if pkgs.is_empty() {
+        return Ok(Some(Versions::new()));
+    }
+    let attrs = pkgs
+        .iter()
+        .map(|pkg| {
+            format!(
+                "\"{}\" = get (if pkgs ? {} then pkgs.{}.version or null else null);",
+                pkg, pkg, pkg
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let versions = crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(let \
+                    pkgs = {}; \
+                    get = v: \
+                      let r = builtins.tryEval (builtins.deepSeq v v); \
+                      in if r.success && builtins.isString r.value then r.value else null; \
+                  in {{ {} }})",
+                crate::nixpkgs(),
+                attrs
+            ),
+        ],
+    )
+    .with_context(|| format!("evaluating the {} versions of the bumped packages", version))?;
+    versions
+        .map(|versions| {
+            serde_json::from_value(versions)
+                .with_context(|| format!("parsing the {} versions of the bumped packages", version))
+        })
+        .transpose()
+}
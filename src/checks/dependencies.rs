@@ -0,0 +1,338 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Maximum number of dependencies listed per lockfile and category
+const MAX_LISTED: usize = 100;
+
+/// Directories holding the sources of dependencies, whose lockfiles are
+/// not those of the package
+const IGNORED_DIRS: &[&str] = &["node_modules", "vendor", "third_party", ".git"];
+
+/// The versions of each dependency locked by a lockfile
+type Deps = BTreeMap<String, BTreeSet<String>>;
+
+/// The dependencies of each lockfile found in the source, by path
+/// relative to the root of the source
+type Lockfiles = BTreeMap<PathBuf, Deps>;
+
+pub struct Chk {
+    pkg: String,
+    sources: Rc<crate::sources::Shared>,
+    lockfiles_before: Option<Lockfiles>,
+    lockfiles_after: Option<Lockfiles>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, sources: Rc<crate::sources::Shared>) -> Chk {
+        Chk {
+            pkg,
+            sources,
+            lockfiles_before: None,
+            lockfiles_after: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0xc936fc0ea456486f87f2b9d8f53a27e0),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("dependencies({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.lockfiles_before = lockfiles_of(killer, "base", &self.sources)?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.lockfiles_after = lockfiles_of(killer, "to-check", &self.sources)?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.lockfiles_before, &self.lockfiles_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => return format!("**dependencies of {}:** 😢 check was interrupted", self.pkg),
+        };
+        if before.is_empty() && after.is_empty() {
+            return format!("**dependencies of {}:** ✔ no lockfile found", self.pkg);
+        }
+
+        let empty = Deps::new();
+        let mut summary = Vec::new();
+        let mut details = String::new();
+        for lockfile in before.keys().chain(after.keys()).collect::<BTreeSet<_>>() {
+            let deps_before = before.get(lockfile).unwrap_or(&empty);
+            let deps_after = after.get(lockfile).unwrap_or(&empty);
+            let changes = changes(deps_before, deps_after);
+            if changes.is_empty() {
+                continue;
+            }
+            summary.push(format!(
+                "`{}`: {} added, {} removed, {} upgraded, {} downgraded",
+                lockfile.display(),
+                changes.added.len(),
+                changes.removed.len(),
+                changes.upgraded.len(),
+                changes.downgraded.len()
+            ));
+            details += &format!("\n**`{}`:**\n", lockfile.display());
+            details += &list("added", &changes.added);
+            details += &list("removed", &changes.removed);
+            details += &list("upgraded", &changes.upgraded);
+            details += &list("downgraded", &changes.downgraded);
+        }
+
+        if summary.is_empty() {
+            return format!(
+                "**dependencies of {}:** ✔ the locked dependencies did not change",
+                self.pkg
+            );
+        }
+        let mut res = format!("**dependencies of {}:**\n", self.pkg);
+        for s in summary {
+            res += &format!("  * {}\n", s);
+        }
+        res += "\n<details><summary>changed dependencies</summary>\n";
+        res += &details;
+        res += "\n</details>\n";
+        res
+    }
+}
+
+/// Dependency changes, as lines of the report
+struct Changes {
+    added: Vec<String>,
+    removed: Vec<String>,
+    upgraded: Vec<String>,
+    downgraded: Vec<String>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+    }
+}
+
+fn changes(before: &Deps, after: &Deps) -> Changes {
+    let versions = |v: &BTreeSet<String>| v.iter().cloned().collect::<Vec<_>>().join(", ");
+    let mut res = Changes {
+        added: Vec::new(),
+        removed: Vec::new(),
+        upgraded: Vec::new(),
+        downgraded: Vec::new(),
+    };
+    for (name, v) in after {
+        match before.get(name) {
+            None => res.added.push(format!("`{}` {}", name, versions(v))),
+            Some(old) if old != v => {
+                let line = format!("`{}` {} → {}", name, versions(old), versions(v));
+                let newest = |v: &BTreeSet<String>| v.iter().map(|v| version_key(v)).max();
+                if newest(v) >= newest(old) {
+                    res.upgraded.push(line);
+                } else {
+                    res.downgraded.push(line);
+                }
+            }
+            Some(_) => (),
+        }
+    }
+    for (name, v) in before {
+        if !after.contains_key(name) {
+            res.removed.push(format!("`{}` {}", name, versions(v)));
+        }
+    }
+    res
+}
+
+/// Returns the numeric components of the version, for comparison
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|c| !c.is_empty())
+        .map(|c| c.parse().unwrap_or(u64::MAX))
+        .collect()
+}
+
+fn list(title: &str, items: &[String]) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    let mut res = format!("  * *{}:*\n", title);
+    for i in items.iter().take(MAX_LISTED) {
+        res += &format!("    * {}\n", i);
+    }
+    if items.len() > MAX_LISTED {
+        res += &format!("    * … and {} more\n", items.len() - MAX_LISTED);
+    }
+    res
+}
+
+/// Parses the lockfiles of the source of the package
+fn lockfiles_of(
+    killer: &Receiver<()>,
+    version: &str,
+    sources: &crate::sources::Shared,
+) -> anyhow::Result<Option<Lockfiles>> {
+    let source = match sources.get(killer, version)? {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+    let tree = match &source.tree {
+        Some(tree) => tree,
+        None => return Ok(Some(Lockfiles::new())),
+    };
+    let mut res = Lockfiles::new();
+    for f in crate::sources::files(tree)? {
+        if f.components()
+            .any(|c| IGNORED_DIRS.iter().any(|d| c.as_os_str() == *d))
+        {
+            continue;
+        }
+        let parse: fn(&str) -> anyhow::Result<Deps> = match f.file_name().and_then(|n| n.to_str()) {
+            Some("Cargo.lock") => parse_cargo_lock,
+            Some("go.sum") => parse_go_sum,
+            Some("package-lock.json") => parse_package_lock,
+            Some("yarn.lock") => parse_yarn_lock,
+            _ => continue,
+        };
+        // Lockfiles that fail parsing are likely test fixtures
+        if let Ok(deps) = read(&tree.join(&f)).and_then(|text| parse(&text)) {
+            res.insert(f, deps);
+        }
+    }
+    Ok(Some(res))
+}
+
+fn read(path: &Path) -> anyhow::Result<String> {
+    let text = std::fs::read(path).with_context(|| format!("reading {:?}", path))?;
+    Ok(String::from_utf8_lossy(&text).into_owned())
+}
+
+fn insert(deps: &mut Deps, name: &str, version: &str) {
+    deps.entry(name.to_string())
+        .or_default()
+        .insert(version.to_string());
+}
+
+/// Returns the value of a `key = "value"` TOML line
+fn toml_string<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let rest = line
+        .strip_prefix(key)?
+        .trim_start()
+        .strip_prefix('=')?
+        .trim();
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn parse_cargo_lock(text: &str) -> anyhow::Result<Deps> {
+    let mut res = Deps::new();
+    let mut name = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some(n) = toml_string(line, "name") {
+            name = Some(n);
+        } else if let (Some(n), Some(v)) = (name, toml_string(line, "version")) {
+            insert(&mut res, n, v);
+            name = None;
+        }
+    }
+    Ok(res)
+}
+
+fn parse_go_sum(text: &str) -> anyhow::Result<Deps> {
+    let mut res = Deps::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        if let (Some(module), Some(version)) = (words.next(), words.next()) {
+            let version = version.strip_suffix("/go.mod").unwrap_or(version);
+            insert(&mut res, module, version);
+        }
+    }
+    Ok(res)
+}
+
+fn parse_package_lock(text: &str) -> anyhow::Result<Deps> {
+    let lock: serde_json::Value = serde_json::from_str(text).context("parsing the json")?;
+    let mut res = Deps::new();
+    // Lockfile versions 2 and 3
+    if let Some(packages) = lock["packages"].as_object() {
+        for (path, package) in packages {
+            let name = match path.rfind("node_modules/") {
+                Some(i) => &path[i + "node_modules/".len()..],
+                None => continue,
+            };
+            if let Some(version) = package["version"].as_str() {
+                insert(&mut res, name, version);
+            }
+        }
+        return Ok(res);
+    }
+    // Lockfile version 1
+    let mut to_visit = vec![&lock["dependencies"]];
+    while let Some(deps) = to_visit.pop() {
+        if let Some(deps) = deps.as_object() {
+            for (name, dep) in deps {
+                if let Some(version) = dep["version"].as_str() {
+                    insert(&mut res, name, version);
+                }
+                to_visit.push(&dep["dependencies"]);
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn parse_yarn_lock(text: &str) -> anyhow::Result<Deps> {
+    let mut res = Deps::new();
+    let mut name = None;
+    for line in text.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(' ') {
+            // Entries start with their specifiers, like `"a@^1.0", a@^1.1:`
+            let spec = line
+                .trim_end_matches(':')
+                .split(',')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_matches('"');
+            name = match spec.rfind('@') {
+                Some(i) if i > 0 => Some(spec[..i].to_string()),
+                _ => None,
+            };
+            continue;
+        }
+        let line = line.trim();
+        let version = line
+            .strip_prefix("version ")
+            .or_else(|| line.strip_prefix("version: "));
+        if let (Some(n), Some(v)) = (&name, version) {
+            insert(&mut res, n, v.trim().trim_matches('"'));
+            name = None;
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,190 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::collections::BTreeMap;
+
+/// Boolean attributes that enable running a test suite
+const SWITCHES: &[&str] = &["doCheck", "doInstallCheck"];
+
+/// List attributes whose items disable tests
+const DISABLING_LISTS: &[&str] = &["disabledTests", "disabledTestPaths", "pytestFlagsArray"];
+
+/// List attributes whose items enable tests
+const ENABLING_LISTS: &[&str] = &["pythonImportsCheck"];
+
+/// The values of `SWITCHES`, `DISABLING_LISTS` and `ENABLING_LISTS` for a
+/// package, null when unset or failing to evaluate
+type Settings = BTreeMap<String, serde_json::Value>;
+
+pub struct Chk {
+    pkg: String,
+    settings_before: Option<Settings>,
+    settings_after: Option<Settings>,
+}
+
+impl Chk {
+    pub fn new(pkg: String) -> Chk {
+        Chk {
+            pkg,
+            settings_before: None,
+            settings_after: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x3fb1b1b45afc4ee590cc59f734170bfe),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("disabled-tests({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.settings_before = settings(killer, "base", &self.pkg)?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.settings_after = settings(killer, "to-check", &self.pkg)?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.settings_before, &self.settings_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => {
+                return format!(
+                    "**disabled tests of {}:** 😢 check was interrupted",
+                    self.pkg
+                )
+            }
+        };
+
+        let mut lines = Vec::new();
+        for attr in SWITCHES {
+            match (is_on(before.get(*attr)), is_on(after.get(*attr))) {
+                (true, false) => lines.push(format!("😢 `{}` was turned off", attr)),
+                (false, true) => lines.push(format!("💚 `{}` was turned on", attr)),
+                _ => (),
+            }
+        }
+        for attr in DISABLING_LISTS {
+            let (added, removed) = list_changes(before.get(*attr), after.get(*attr));
+            if !added.is_empty() {
+                lines.push(format!("😢 added to `{}`: {}", attr, added.join(", ")));
+            }
+            if !removed.is_empty() {
+                lines.push(format!(
+                    "💚 removed from `{}`: {}",
+                    attr,
+                    removed.join(", ")
+                ));
+            }
+        }
+        for attr in ENABLING_LISTS {
+            let (added, removed) = list_changes(before.get(*attr), after.get(*attr));
+            if !removed.is_empty() {
+                lines.push(format!(
+                    "😢 removed from `{}`: {}",
+                    attr,
+                    removed.join(", ")
+                ));
+            }
+            if !added.is_empty() {
+                lines.push(format!("💚 added to `{}`: {}", attr, added.join(", ")));
+            }
+        }
+
+        if lines.is_empty() {
+            return format!(
+                "**disabled tests of {}:** ✔ no change to the tests that are run",
+                self.pkg
+            );
+        }
+        let mut res = format!("**disabled tests of {}:**\n", self.pkg);
+        for l in lines {
+            res += &format!("  * {}\n", l);
+        }
+        res
+    }
+}
+
+fn settings(killer: &Receiver<()>, version: &str, pkg: &str) -> anyhow::Result<Option<Settings>> {
+    let attrs = SWITCHES
+        .iter()
+        .chain(DISABLING_LISTS)
+        .chain(ENABLING_LISTS)
+        .map(|a| format!("\"{}\"", a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(let \
+                    pkg = {}.{}; \
+                    get = attr: \
+                      let v = builtins.tryEval (builtins.deepSeq (pkg.${{attr}} or null) (pkg.${{attr}} or null)); \
+                      in if v.success then v.value else null; \
+                  in builtins.listToAttrs (map (attr: {{ name = attr; value = get attr; }}) [ {} ]))",
+                crate::nixpkgs(),
+                pkg,
+                attrs
+            ),
+        ],
+    )
+    .with_context(|| format!("evaluating the test settings of {} version of {}", version, pkg))?
+    .map(|v| {
+        serde_json::from_value(v)
+            .with_context(|| format!("parsing the test settings of {} version of {}", version, pkg))
+    })
+    .transpose()
+}
+
+fn is_on(value: Option<&serde_json::Value>) -> bool {
+    matches!(value, Some(serde_json::Value::Bool(true)))
+}
+
+/// Returns the items added to and removed from a list attribute, as
+/// markdown code
+fn list_changes(
+    before: Option<&serde_json::Value>,
+    after: Option<&serde_json::Value>,
+) -> (Vec<String>, Vec<String>) {
+    let items = |v: Option<&serde_json::Value>| -> Vec<String> {
+        match v {
+            Some(serde_json::Value::Array(items)) => items
+                .iter()
+                .map(|i| match i {
+                    serde_json::Value::String(s) => s.clone(),
+                    i => i.to_string(),
+                })
+                .collect(),
+            Some(serde_json::Value::String(s)) => vec![s.clone()],
+            _ => Vec::new(),
+        }
+    };
+    let before = items(before);
+    let after = items(after);
+    let added = after
+        .iter()
+        .filter(|i| !before.contains(i))
+        .map(|i| format!("`{}`", i))
+        .collect();
+    let removed = before
+        .iter()
+        .filter(|i| !after.contains(i))
+        .map(|i| format!("`{}`", i))
+        .collect();
+    (added, removed)
+}
@@ -0,0 +1,164 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::collections::BTreeMap;
+
+/// Attributes of the packages that usually are fixed-output derivations
+const FOD_ATTRS: &[&str] = &[
+    "src",
+    "cargoDeps",
+    "goModules",
+    "go-modules",
+    "npmDeps",
+    "yarnOfflineCache",
+    "pnpmDeps",
+    "offlineCache",
+    "mavenDeps",
+    "composerRepository",
+];
+
+/// A fixed-output derivation of a package
+#[derive(serde::Deserialize)]
+struct Fod {
+    hash: String,
+    /// What the derivation is fetched from: its name, urls, revision and
+    /// the hash of its own source, for derivations vendoring dependencies
+    origin: serde_json::Value,
+}
+
+pub struct Chk {
+    pkg: String,
+    fods_before: Option<BTreeMap<String, Fod>>,
+    fods_after: Option<BTreeMap<String, Fod>>,
+}
+
+impl Chk {
+    pub fn new(pkg: String) -> Chk {
+        Chk {
+            pkg,
+            fods_before: None,
+            fods_after: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x3fd7c243b60e4f0caca16989b85374d2),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("fod-hashes({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.fods_before = fods(killer, "base", &self.pkg)?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.fods_after = fods(killer, "to-check", &self.pkg)?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.fods_before, &self.fods_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => {
+                return format!(
+                    "**fixed-output hashes of {}:** 😢 check was interrupted",
+                    self.pkg
+                )
+            }
+        };
+
+        let mut hash_only = Vec::new();
+        for (attr, fod_after) in after {
+            if let Some(fod_before) = before.get(attr) {
+                if fod_before.hash != fod_after.hash && fod_before.origin == fod_after.origin {
+                    hash_only.push(attr);
+                }
+            }
+        }
+
+        if hash_only.is_empty() {
+            return format!(
+                "**fixed-output hashes of {}:** ✔ no hash changed without its source changing",
+                self.pkg
+            );
+        }
+        let mut res = format!("**fixed-output hashes of {}:**\n", self.pkg);
+        for attr in hash_only {
+            res += &format!(
+                "  * 😢 `{}` changed hash while its URL and revision stayed the same, \
+                 was the previous hash wrong or did upstream re-tag?\n",
+                attr
+            );
+        }
+        res
+    }
+}
+
+/// Evaluates the fixed-output derivations of the package, by attribute
+fn fods(
+    killer: &Receiver<()>,
+    version: &str,
+    pkg: &str,
+) -> anyhow::Result<Option<BTreeMap<String, Fod>>> {
+    let attrs = FOD_ATTRS
+        .iter()
+        .map(|a| format!("\"{}\"", a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(let \
+                    pkg = {}; \
+                    fod = attr: \
+                      let \
+                        d = pkg.${{attr}}; \
+                        info = {{ \
+                          hash = d.outputHash; \
+                          origin = {{ \
+                            name = d.name or null; \
+                            urls = d.urls or [ (d.url or null) ]; \
+                            rev = d.rev or null; \
+                            src = if builtins.isAttrs (d.src or null) then d.src.outputHash or d.src.outPath else null; \
+                          }}; \
+                        }}; \
+                        res = builtins.tryEval (builtins.deepSeq info info); \
+                      in if builtins.isAttrs (pkg.${{attr}} or null) && pkg.${{attr}} ? outputHash && res.success \
+                        then [ {{ name = attr; value = res.value; }} ] \
+                        else []; \
+                  in builtins.listToAttrs (builtins.concatMap fod [ {} ]))",
+                crate::nix_eval_for(pkg),
+                attrs
+            ),
+        ],
+    )
+    .with_context(|| {
+        format!(
+            "evaluating the fixed-output derivations of {} version of {}",
+            version, pkg
+        )
+    })?
+    .map(|fods| {
+        serde_json::from_value(fods).with_context(|| {
+            format!(
+                "parsing the fixed-output derivations of {} version of {}",
+                version, pkg
+            )
+        })
+    })
+    .transpose()
+}
@@ -0,0 +1,184 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{collections::BTreeMap, path::Path, rc::Rc};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Relro {
+    None,
+    Partial,
+    Full,
+}
+
+struct Hardening {
+    /// PIE only makes sense for executables, not for libraries
+    executable: bool,
+    pie: bool,
+    relro: Relro,
+    nx: bool,
+    canary: bool,
+    fortify: bool,
+    stripped: bool,
+}
+
+impl Hardening {
+    fn of(elf: &crate::elf::Elf) -> Hardening {
+        Hardening {
+            executable: elf.elf_type == crate::elf::ET_EXEC || elf.interpreter.is_some(),
+            pie: elf.is_pie(),
+            relro: match (elf.has_relro(), elf.bind_now) {
+                (false, _) => Relro::None,
+                (true, false) => Relro::Partial,
+                (true, true) => Relro::Full,
+            },
+            nx: elf.has_nx(),
+            canary: elf
+                .symbols
+                .iter()
+                .any(|s| s == "__stack_chk_fail" || s == "__stack_chk_guard"),
+            fortify: elf
+                .symbols
+                .iter()
+                .any(|s| s.starts_with("__") && s.ends_with("_chk") && s != "__stack_chk_fail"),
+            stripped: !elf
+                .sections
+                .iter()
+                .any(|s| s == ".symtab" || s.starts_with(".debug_")),
+        }
+    }
+
+    /// Lists the protections that were in `self` but are no longer in
+    /// `after`
+    fn lost(&self, after: &Hardening) -> Vec<&'static str> {
+        let mut res = Vec::new();
+        if after.executable && self.pie && !after.pie {
+            res.push("PIE");
+        }
+        if after.relro < self.relro {
+            res.push(match self.relro {
+                Relro::Full => "full RELRO",
+                _ => "RELRO",
+            });
+        }
+        if self.nx && !after.nx {
+            res.push("NX");
+        }
+        if self.canary && !after.canary {
+            res.push("stack canary");
+        }
+        if self.fortify && !after.fortify {
+            res.push("FORTIFY");
+        }
+        res
+    }
+}
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    hardening_before: BTreeMap<String, Hardening>,
+    hardening_after: BTreeMap<String, Hardening>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            hardening_before: BTreeMap::new(),
+            hardening_after: BTreeMap::new(),
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0xed6f5576ea9f4d75a329a4e8b1f98822),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("hardening({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        self.hardening_before = hardening(self.outs_dir.path(), "base")
+            .with_context(|| format!("checking the base hardening of {}", self.pkg))?;
+        self.hardening_after = hardening(self.outs_dir.path(), "to-check")
+            .with_context(|| format!("checking the to-check hardening of {}", self.pkg))?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        if self.hardening_after.is_empty() {
+            return String::new();
+        }
+
+        let mut res = format!("**hardening of {}:**", self.pkg);
+        let mut weakened = String::new();
+        for (file, after) in &self.hardening_after {
+            if let Some(before) = self.hardening_before.get(file) {
+                let lost = before.lost(after);
+                if !lost.is_empty() {
+                    weakened += &format!("  * ❌ `{}` lost {}\n", file, lost.join(", "));
+                }
+            }
+        }
+        if weakened.is_empty() {
+            res += " ✔ no binary got weaker hardening\n";
+        } else {
+            res += "\n";
+            res += &weakened;
+        }
+
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        res += "\n<details><summary>hardening of the to-check binaries</summary>\n\n";
+        res += "| file | PIE | RELRO | NX | stack canary | FORTIFY | stripped |\n";
+        res += "|------|-----|-------|----|--------------|---------|----------|\n";
+        for (file, h) in &self.hardening_after {
+            res += &format!(
+                "| `{}` | {} | {} | {} | {} | {} | {} |\n",
+                file,
+                if h.executable { yes_no(h.pie) } else { "n/a" },
+                match h.relro {
+                    Relro::None => "none",
+                    Relro::Partial => "partial",
+                    Relro::Full => "full",
+                },
+                yes_no(h.nx),
+                yes_no(h.canary),
+                yes_no(h.fortify),
+                yes_no(h.stripped),
+            );
+        }
+        res += "\n</details>\n";
+        res
+    }
+}
+
+fn hardening(outs_dir: &Path, version: &str) -> anyhow::Result<BTreeMap<String, Hardening>> {
+    let mut res = BTreeMap::new();
+    for (name, f) in crate::checks::build::output_files(outs_dir, version)? {
+        let is_file = std::fs::symlink_metadata(&f)
+            .with_context(|| format!("reading the metadata of {:?}", f))?
+            .is_file();
+        if !is_file {
+            continue;
+        }
+        if let Some(elf) = crate::elf::read(&f)? {
+            if elf.elf_type == crate::elf::ET_EXEC || elf.elf_type == crate::elf::ET_DYN {
+                res.insert(name, Hardening::of(&elf));
+            }
+        }
+    }
+    Ok(res)
+}
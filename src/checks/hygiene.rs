@@ -0,0 +1,165 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    rc::Rc,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    UnpatchedShebang,
+    DanglingSymlink,
+    SymlinkOutOfStore,
+    EmptyDirectory,
+}
+
+/// Maps (kind, file) to a human-readable explanation of the issue
+type Findings = BTreeMap<(Kind, String), String>;
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    findings_before: Option<Findings>,
+    findings_after: Findings,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            findings_before: None,
+            findings_after: Findings::new(),
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x5dac26ecdf2540b58dafedda696c6a6b),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("hygiene({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        if !crate::checks::build::outputs(self.outs_dir.path(), "base")?.is_empty() {
+            self.findings_before = Some(
+                find_issues(self.outs_dir.path(), "base")
+                    .with_context(|| format!("checking the base outputs of {}", self.pkg))?,
+            );
+        }
+        self.findings_after = find_issues(self.outs_dir.path(), "to-check")
+            .with_context(|| format!("checking the to-check outputs of {}", self.pkg))?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        if self.findings_after.is_empty() {
+            return format!("**output hygiene of {}:** ✔ no issue found", self.pkg);
+        }
+        // Paths often contain the version, eg. `share/doc/foo-1.2`
+        let blank = crate::version_blanker();
+        let before = self.findings_before.as_ref().map(|before| {
+            before
+                .keys()
+                .map(|(kind, file)| (*kind, blank(file)))
+                .collect::<BTreeSet<_>>()
+        });
+        let mut res = format!("**output hygiene of {}:**\n", self.pkg);
+        for (key, issue) in &self.findings_after {
+            let emoji = match &before {
+                Some(before) if !before.contains(&(key.0, blank(&key.1))) => "❌",
+                _ => "😢",
+            };
+            res += &format!("  * {} `{}` {}\n", emoji, key.1, issue);
+        }
+        res
+    }
+}
+
+fn find_issues(outs_dir: &Path, version: &str) -> anyhow::Result<Findings> {
+    let mut res = Findings::new();
+    for (file, path) in crate::checks::build::output_files(outs_dir, version)? {
+        let meta = std::fs::symlink_metadata(&path)
+            .with_context(|| format!("reading the metadata of {:?}", path))?;
+
+        if meta.file_type().is_symlink() {
+            let target =
+                std::fs::read_link(&path).with_context(|| format!("reading link {:?}", path))?;
+            if target.is_absolute() && !target.starts_with("/nix/store") {
+                res.insert(
+                    (Kind::SymlinkOutOfStore, file),
+                    format!("points out of the store, to `{}`", target.display()),
+                );
+            } else if !path.exists() {
+                res.insert(
+                    (Kind::DanglingSymlink, file),
+                    format!("is a dangling symlink to `{}`", target.display()),
+                );
+            }
+            continue;
+        }
+
+        if meta.is_dir() {
+            let is_empty = std::fs::read_dir(&path)
+                .with_context(|| format!("listing directory {:?}", path))?
+                .next()
+                .is_none();
+            if is_empty {
+                res.insert(
+                    (Kind::EmptyDirectory, file),
+                    String::from("is an empty directory"),
+                );
+            }
+        } else if meta.permissions().mode() & 0o111 != 0 {
+            // Like patchShebangs, only look at executables: other files are
+            // not run through their shebang
+            if let Some(interpreter) = shebang(&path)? {
+                if !interpreter.starts_with("/nix/store") {
+                    res.insert(
+                        (Kind::UnpatchedShebang, file),
+                        format!(
+                            "has a shebang pointing out of the store, to `{}`",
+                            interpreter
+                        ),
+                    );
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Returns the interpreter of the script, if the file has a shebang
+fn shebang(path: &Path) -> anyhow::Result<Option<String>> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .with_context(|| format!("opening {:?}", path))?
+        .take(256)
+        .read_to_end(&mut head)
+        .with_context(|| format!("reading {:?}", path))?;
+    if !head.starts_with(b"#!") {
+        return Ok(None);
+    }
+    let line = head[2..].split(|&b| b == b'\n').next().unwrap_or(&[]);
+    Ok(String::from_utf8_lossy(line)
+        .split_whitespace()
+        .next()
+        .map(|i| i.to_string()))
+}
@@ -0,0 +1,119 @@
+use crossbeam_channel::Receiver;
+
+pub struct Chk {
+    pkg: String,
+    meta_before: Option<crate::meta::Meta>,
+    meta_after: Option<crate::meta::Meta>,
+}
+
+impl Chk {
+    pub fn new(pkg: String) -> Chk {
+        Chk {
+            pkg,
+            meta_before: None,
+            meta_after: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x3b6f7a62e0d54c4cb73ab7be0987f046),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("licensing({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.meta_before = crate::meta::eval(killer, "base", &self.pkg)?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.meta_after = crate::meta::eval(killer, "to-check", &self.pkg)?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.meta_before, &self.meta_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => {
+                return format!(
+                    "**license and maintainers of {}:** 😢 check was interrupted",
+                    self.pkg
+                )
+            }
+        };
+
+        let mut lines = Vec::new();
+        if before.licenses != after.licenses {
+            let names = |licenses: &[crate::meta::License]| {
+                if licenses.is_empty() {
+                    return "none".to_string();
+                }
+                licenses
+                    .iter()
+                    .map(|l| format!("`{}`", l.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            lines.push(format!(
+                "😢 **the license changed:** {} → {}",
+                names(&before.licenses),
+                names(&after.licenses)
+            ));
+            let all_free = |licenses: &[crate::meta::License]| licenses.iter().all(|l| l.free);
+            let all_redistributable =
+                |licenses: &[crate::meta::License]| licenses.iter().all(|l| l.redistributable);
+            if all_free(&before.licenses) && !all_free(&after.licenses) {
+                lines.push("❌ **the package became unfree**".to_string());
+            } else if !all_free(&before.licenses) && all_free(&after.licenses) {
+                lines.push("💚 the package became free".to_string());
+            }
+            if all_redistributable(&before.licenses) && !all_redistributable(&after.licenses) {
+                lines.push("❌ **the package is no longer redistributable**".to_string());
+            }
+        }
+
+        let removed = before
+            .maintainers
+            .iter()
+            .filter(|m| !after.maintainers.contains(m))
+            .map(|m| format!("`{}`", m.name()))
+            .collect::<Vec<_>>();
+        let added = after
+            .maintainers
+            .iter()
+            .filter(|m| !before.maintainers.contains(m))
+            .map(|m| format!("`{}`", m.name()))
+            .collect::<Vec<_>>();
+        if !before.maintainers.is_empty() && after.maintainers.is_empty() {
+            lines.push(format!(
+                "❌ **the package lost all its maintainers:** {}",
+                removed.join(", ")
+            ));
+        } else if !removed.is_empty() {
+            lines.push(format!("😢 removed maintainers: {}", removed.join(", ")));
+        }
+        if !added.is_empty() {
+            lines.push(format!("✔ added maintainers: {}", added.join(", ")));
+        }
+
+        if lines.is_empty() {
+            return format!("**license and maintainers of {}:** ✔ unchanged", self.pkg);
+        }
+        let mut res = format!("**license and maintainers of {}:**\n", self.pkg);
+        for l in lines {
+            res += &format!("  * {}\n", l);
+        }
+        res
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    missing_before: Option<BTreeSet<(String, String)>>,
+    missing_after: BTreeSet<(String, String)>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            missing_before: None,
+            missing_after: BTreeSet::new(),
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0xbb9fe422263440368502580a976cf6c1),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("linkage({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        if !crate::checks::build::outputs(self.outs_dir.path(), "base")?.is_empty() {
+            self.missing_before = Some(
+                missing_libraries(self.outs_dir.path(), "base")
+                    .with_context(|| format!("checking the base libraries of {}", self.pkg))?,
+            );
+        }
+        self.missing_after = missing_libraries(self.outs_dir.path(), "to-check")
+            .with_context(|| format!("checking the to-check libraries of {}", self.pkg))?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let no_missing_before = self.missing_before.iter().all(|m| m.is_empty());
+        if self.missing_after.is_empty() && no_missing_before {
+            return format!(
+                "**shared libraries of {}:** ✔ all needed libraries are found",
+                self.pkg
+            );
+        }
+        // Library and file names usually contain the version, eg.
+        // `libfoo.so.1.2.3`
+        let blank = crate::version_blanker();
+        let blank_all = |missing: &BTreeSet<(String, String)>| {
+            missing
+                .iter()
+                .map(|(file, lib)| (blank(file), blank(lib)))
+                .collect::<BTreeSet<_>>()
+        };
+        let before = self.missing_before.as_ref().map(blank_all);
+        let after = blank_all(&self.missing_after);
+        let mut res = format!("**shared libraries of {}:**\n", self.pkg);
+        for (file, lib) in &self.missing_after {
+            match &before {
+                None => res += &format!("  * 😢 `{}` cannot find `{}`\n", file, lib),
+                Some(before) if before.contains(&(blank(file), blank(lib))) => {
+                    res += &format!("  * 😢 `{}` still cannot find `{}`\n", file, lib)
+                }
+                Some(_) => res += &format!("  * ❌ `{}` stopped finding `{}`\n", file, lib),
+            }
+        }
+        if let Some(before) = &self.missing_before {
+            for (file, lib) in before {
+                if !after.contains(&(blank(file), blank(lib))) {
+                    res += &format!("  * 💚 `{}` no longer misses `{}`\n", file, lib);
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Returns the (file, library) pairs of the ELF files in the outputs
+/// whose needed library cannot be found in the store
+fn missing_libraries(outs_dir: &Path, version: &str) -> anyhow::Result<BTreeSet<(String, String)>> {
+    // Find all the dynamically-linked ELF files
+    let mut elves = Vec::new();
+    for (name, f) in crate::checks::build::output_files(outs_dir, version)? {
+        let is_file = std::fs::symlink_metadata(&f)
+            .with_context(|| format!("reading the metadata of {:?}", f))?
+            .is_file();
+        if !is_file {
+            continue;
+        }
+        if let Some(elf) = crate::elf::read(&f)? {
+            if elf.elf_type == crate::elf::ET_EXEC || elf.elf_type == crate::elf::ET_DYN {
+                elves.push((name, f, elf));
+            }
+        }
+    }
+
+    // Libraries without RUNPATH entry for the libc rely on the dynamic
+    // loader finding it next to itself
+    let loader_dirs = elves
+        .iter()
+        .filter_map(|(_, _, elf)| elf.interpreter.as_ref())
+        .filter_map(|i| Path::new(i).parent().map(|p| p.to_path_buf()))
+        .collect::<BTreeSet<PathBuf>>();
+
+    // And resolve their needed libraries
+    let mut res = BTreeSet::new();
+    for (name, path, elf) in elves {
+        let origin = path.parent().expect("walked file has no parent");
+        let search_path = elf
+            .runpath
+            .as_ref()
+            .or(elf.rpath.as_ref())
+            .map(|p| {
+                p.split(':')
+                    .map(|d| {
+                        let d = d.replace("${ORIGIN}", "$ORIGIN");
+                        PathBuf::from(d.replace("$ORIGIN", &origin.to_string_lossy()))
+                    })
+                    .filter(|d| d.starts_with("/nix/store"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(Vec::new);
+        for lib in elf.needed {
+            let found = if lib.contains('/') {
+                Path::new(&lib).exists()
+            } else {
+                search_path
+                    .iter()
+                    .chain(loader_dirs.iter())
+                    .any(|d| d.join(&lib).exists())
+            };
+            if !found {
+                res.insert((name.clone(), lib));
+            }
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,157 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{path::Path, rc::Rc};
+
+struct Found {
+    main_program: Option<String>,
+    /// What `lib.getExe` returns, if it succeeds
+    get_exe: Option<String>,
+    /// The binaries `lib.getExe` picks from, or `None` without a `bin`
+    /// directory
+    binaries: Option<Vec<String>>,
+}
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    found: Option<Found>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            found: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0xd808cb4688ac4026a230ae160bf96a42),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("main-program({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        let meta = match crate::meta::eval(killer, "to-check", &self.pkg)? {
+            Some(meta) => meta,
+            None => return Ok(()),
+        };
+        let get_exe = match crate::nix(
+            killer,
+            &[
+                "eval",
+                "--json",
+                &format!(
+                    "(let \
+                        pkgs = {}; \
+                        exe = builtins.tryEval (pkgs.lib.getExe pkgs.{}); \
+                      in if pkgs.lib ? getExe && exe.success then exe.value else null)",
+                    crate::nixpkgs(),
+                    self.pkg
+                ),
+            ],
+        )
+        .with_context(|| format!("evaluating lib.getExe for {}", self.pkg))?
+        {
+            Some(get_exe) => get_exe.as_str().map(|e| e.to_string()),
+            None => return Ok(()),
+        };
+        let binaries =
+            crate::checks::run_binaries::list_main_binaries(self.outs_dir.path(), "to-check")
+                .with_context(|| format!("listing the to-check binaries for {}", self.pkg))?
+                .map(|binaries| {
+                    let mut binaries = binaries.into_iter().collect::<Vec<_>>();
+                    binaries.sort();
+                    binaries
+                });
+        self.found = Some(Found {
+            main_program: meta.main_program,
+            get_exe,
+            binaries,
+        });
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let found = match &self.found {
+            Some(found) => found,
+            None => return format!("**main program of {}:** 😢 check was interrupted", self.pkg),
+        };
+        let res = format!("**main program of {}:**", self.pkg);
+        let binaries = match &found.binaries {
+            Some(binaries) => binaries,
+            None => {
+                return match &found.main_program {
+                    Some(main) => format!(
+                        "{} ❌ `meta.mainProgram` is `{}` but there is no `bin` directory",
+                        res, main
+                    ),
+                    None => format!("{} ✔ there are no binaries", res),
+                }
+            }
+        };
+        let listed = binaries
+            .iter()
+            .map(|b| format!("`{}`", b))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match &found.main_program {
+            Some(main) if binaries.contains(main) => {
+                format!("{} ✔ `{}` is in `bin`", res, main)
+            }
+            Some(main) if binaries.is_empty() => format!(
+                "{} ❌ `meta.mainProgram` is `{}` but `bin` is empty",
+                res, main
+            ),
+            Some(main) => format!(
+                "{} ❌ `meta.mainProgram` is `{}` but `bin` only has {}",
+                res, main, listed
+            ),
+            None if binaries.len() > 1 => format!(
+                "{} 😢 `meta.mainProgram` is not set while `bin` has {}",
+                res, listed
+            ),
+            None => match &found.get_exe {
+                // lib.getExe falls back to the name of the package
+                Some(exe) if !binaries.is_empty() => {
+                    let name = Path::new(exe)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    if binaries.contains(&name) {
+                        format!(
+                            "{} ✔ `lib.getExe` finds `{}`, but setting `meta.mainProgram` would be better",
+                            res, name
+                        )
+                    } else {
+                        format!(
+                            "{} 😢 `meta.mainProgram` is not set and `lib.getExe` guesses `{}`, while `bin` has {}",
+                            res, name, listed
+                        )
+                    }
+                }
+                _ if binaries.is_empty() => format!("{} ✔ there are no binaries", res),
+                _ => format!(
+                    "{} 😢 `meta.mainProgram` is not set and `lib.getExe` fails, while `bin` has {}",
+                    res, listed
+                ),
+            },
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::collections::{BTreeSet, HashSet};
+
+pub struct Chk {
+    pkgs: HashSet<String>,
+    /// The handle of the user running the checks, who need not be mentioned
+    author: Option<String>,
+    handles: Option<BTreeSet<String>>,
+}
+
+impl Chk {
+    pub fn new(pkgs: HashSet<String>, author: Option<String>) -> Chk {
+        Chk {
+            pkgs,
+            author,
+            handles: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid(uuid::Uuid::from_u128(0xfb64239ab2474031ae801c97fbce5c2e))
+    }
+
+    fn name(&self) -> String {
+        "mentions".to_string()
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        let mut handles = BTreeSet::new();
+        for pkg in &self.pkgs {
+            match maintainer_handles(killer, pkg)? {
+                Some(h) => handles.extend(h),
+                None => return Ok(()),
+            }
+        }
+        if let Some(author) = &self.author {
+            handles.retain(|h: &String| !h.eq_ignore_ascii_case(author));
+        }
+        self.handles = Some(handles);
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        match &self.handles {
+            None => "**maintainers:** 😢 check was interrupted".to_string(),
+            Some(handles) if handles.is_empty() => {
+                "**maintainers:** ✔ there is nobody else to mention".to_string()
+            }
+            Some(handles) => format!(
+                "**maintainers:** cc {}",
+                handles
+                    .iter()
+                    .map(|h| format!("@{}", h))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+/// Returns the GitHub handles of the maintainers of the package and of
+/// the members of its teams
+///
+/// Maintainers without a `github` attribute are looked up by email in
+/// the maintainer list.
+fn maintainer_handles(killer: &Receiver<()>, pkg: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let handles = crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(let \
+                    list = builtins.attrValues (import ./maintainers/maintainer-list.nix); \
+                    handle = m: \
+                      if m ? github then m.github \
+                      else let found = builtins.filter (e: m ? email && (e.email or null) == m.email) list; \
+                      in if found != [] then (builtins.head found).github or null else null; \
+                    pkgs = {}; \
+                    meta = if pkgs ? {} then pkgs.{}.meta or {{}} else {{}}; \
+                    people = (meta.maintainers or []) \
+                      ++ builtins.concatMap (t: t.members or []) (meta.teams or []); \
+                    res = builtins.tryEval (builtins.deepSeq (map handle people) (map handle people)); \
+                  in if res.success then res.value else [])",
+                crate::nixpkgs(),
+                pkg,
+                pkg
+            ),
+        ],
+    )
+    .with_context(|| format!("evaluating the maintainers of {}", pkg))?;
+    Ok(handles.map(|handles| {
+        handles
+            .as_array()
+            .map(|handles| {
+                handles
+                    .iter()
+                    .filter_map(|h| h.as_str().map(|h| h.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }))
+}
@@ -0,0 +1,141 @@
+use crossbeam_channel::Receiver;
+use std::{collections::BTreeSet, rc::Rc};
+
+/// A departure from the nixpkgs conventions for `meta`
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Lint {
+    NoDescription,
+    DescriptionStartsWithArticle,
+    NoLicense,
+    NoMaintainers,
+    NoMainProgram,
+    HttpHomepage(String),
+}
+
+impl Lint {
+    fn describe(&self) -> String {
+        match self {
+            Lint::NoDescription => "`meta.description` is missing".to_string(),
+            Lint::DescriptionStartsWithArticle => {
+                "`meta.description` starts with an article".to_string()
+            }
+            Lint::NoLicense => "`meta.license` is missing".to_string(),
+            Lint::NoMaintainers => "`meta.maintainers` is empty".to_string(),
+            Lint::NoMainProgram => {
+                "`meta.mainProgram` is missing while the package has binaries".to_string()
+            }
+            Lint::HttpHomepage(url) => format!("`meta.homepage` uses http: {}", url),
+        }
+    }
+}
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    lints_before: Option<BTreeSet<Lint>>,
+    lints_after: Option<BTreeSet<Lint>>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            lints_before: None,
+            lints_after: None,
+        }
+    }
+
+    fn lints(
+        &self,
+        killer: &Receiver<()>,
+        version: &str,
+    ) -> anyhow::Result<Option<BTreeSet<Lint>>> {
+        let meta = match crate::meta::eval(killer, version, &self.pkg)? {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        let has_binaries =
+            crate::checks::run_binaries::list_main_binaries(self.outs_dir.path(), version)?
+                .map(|bins| !bins.is_empty())
+                .unwrap_or(false);
+
+        let mut res = BTreeSet::new();
+        match &meta.description {
+            None => {
+                res.insert(Lint::NoDescription);
+            }
+            Some(d) => {
+                let first_word = d.split_whitespace().next().unwrap_or("");
+                if ["A", "An", "The"].contains(&first_word) {
+                    res.insert(Lint::DescriptionStartsWithArticle);
+                }
+            }
+        }
+        if meta.licenses.is_empty() {
+            res.insert(Lint::NoLicense);
+        }
+        if meta.maintainers.is_empty() {
+            res.insert(Lint::NoMaintainers);
+        }
+        if has_binaries && meta.main_program.is_none() {
+            res.insert(Lint::NoMainProgram);
+        }
+        for url in &meta.homepages {
+            if url.starts_with("http://") {
+                res.insert(Lint::HttpHomepage(url.clone()));
+            }
+        }
+        Ok(Some(res))
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0xdb1bd1de95b94bc7bcf69a41a0695ab4),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("meta-lint({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.lints_before = self.lints(killer, "base")?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.lints_after = self.lints(killer, "to-check")?;
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.lints_before, &self.lints_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => return format!("**meta of {}:** 😢 check was interrupted", self.pkg),
+        };
+        let fixed = before.difference(after).collect::<Vec<_>>();
+        if after.is_empty() && fixed.is_empty() {
+            return format!("**meta of {}:** ✔ follows the conventions", self.pkg);
+        }
+        let mut res = format!("**meta of {}:**\n", self.pkg);
+        for lint in after {
+            if before.contains(lint) {
+                res += &format!("  * 😢 {}\n", lint.describe());
+            } else {
+                res += &format!("  * ❌ new: {}\n", lint.describe());
+            }
+        }
+        for lint in fixed {
+            res += &format!("  * 💚 fixed: {}\n", lint.describe());
+        }
+        res
+    }
+}
@@ -1,8 +1,26 @@
 pub mod ask_other_tests;
 pub mod ask_pkg_names;
 pub mod build;
+pub mod changelog;
 pub mod closure_size;
+pub mod commit_messages;
 pub mod confirm_contributing;
+pub mod dependencies;
+pub mod disabled_tests;
 pub mod environment;
+pub mod fod_hashes;
+pub mod hardening;
+pub mod hygiene;
+pub mod licensing;
+pub mod linkage;
+pub mod main_program;
+pub mod mentions;
+pub mod meta_lint;
+pub mod new_package;
+pub mod patches;
+pub mod references;
+pub mod removed_package;
+pub mod reproducibility;
 pub mod run_binaries;
 pub mod run_tests;
+pub mod source_diff;
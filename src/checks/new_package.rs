@@ -0,0 +1,204 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{path::Path, rc::Rc};
+
+/// What the new package looks like, in the to-check version
+#[derive(serde::Deserialize)]
+struct Info {
+    version: Option<String>,
+    /// The file defining the package, followed by `:line`
+    position: Option<String>,
+    maintainers: Vec<Maintainer>,
+    has_license: bool,
+    main_program: Option<String>,
+    has_update_script: bool,
+    has_tests: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct Maintainer {
+    name: String,
+    /// Whether the maintainer comes from `maintainers/maintainer-list.nix`
+    listed: bool,
+}
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    commits: Rc<Vec<crate::Commit>>,
+    findings: Option<Vec<String>>,
+}
+
+impl Chk {
+    pub fn new(
+        pkg: String,
+        outs_dir: Rc<tempfile::TempDir>,
+        commits: Rc<Vec<crate::Commit>>,
+    ) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            commits,
+            findings: None,
+        }
+    }
+
+    fn placement(&self, info: &Info) -> anyhow::Result<Option<String>> {
+        // Only top-level packages can go in pkgs/by-name
+        if self.pkg.contains('.') || !Path::new("pkgs/by-name").is_dir() {
+            return Ok(None);
+        }
+        let shard = self.pkg.chars().take(2).collect::<String>().to_lowercase();
+        let expected = format!("pkgs/by-name/{}/{}/package.nix", shard, self.pkg);
+        if Path::new(&expected).is_file() {
+            return Ok(Some(format!("✔ is in `{}`", expected)));
+        }
+        let cwd = std::env::current_dir().context("recovering current working directory")?;
+        let file = info
+            .position
+            .as_ref()
+            .and_then(|p| p.rsplit_once(':').map(|(f, _)| f))
+            .map(|f| {
+                Path::new(f)
+                    .strip_prefix(&cwd)
+                    .map(|f| f.display().to_string())
+                    .unwrap_or_else(|_| f.to_string())
+            });
+        Ok(Some(match file {
+            Some(file) if file.starts_with("pkgs/by-name/") => {
+                format!("❌ is in `{}` instead of `{}`", file, expected)
+            }
+            Some(file) => format!(
+                "😢 is in `{}`, new packages should be in `{}` unless they cannot",
+                file, expected
+            ),
+            None => format!(
+                "😢 could not be located, new packages should be in `{}`",
+                expected
+            ),
+        }))
+    }
+
+    fn commit(&self, info: &Info) -> String {
+        let last_segment = self.pkg.rsplit('.').next().unwrap_or(&self.pkg);
+        let init = self.commits.iter().find_map(|c| {
+            [self.pkg.as_str(), last_segment]
+                .iter()
+                .find_map(|name| c.summary.strip_prefix(&format!("{}: init at ", name)))
+                .map(|version| (c, version.trim()))
+        });
+        match (init, &info.version) {
+            (None, Some(version)) => {
+                format!("❌ no commit is named `{}: init at {}`", self.pkg, version)
+            }
+            (None, None) => format!("❌ no commit is named `{}: init at <version>`", self.pkg),
+            (Some((c, version)), Some(expected)) if version != expected => format!(
+                "😢 commit `{}` does not match the version of the package, {}",
+                c.summary, expected
+            ),
+            (Some((c, _)), _) => format!("✔ commit `{}`", c.summary),
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x816a130b7f6c4eb2874a3c816c2195c1),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("new-package({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        let info = match crate::nix(
+            killer,
+            &[
+                "eval",
+                "--json",
+                &format!(
+                    "(let \
+                        pkg = {}; \
+                        list = builtins.attrValues (import ./maintainers/maintainer-list.nix); \
+                        get = default: v: \
+                          let r = builtins.tryEval (builtins.deepSeq v v); \
+                          in if r.success then r.value else default; \
+                        maintainer = m: {{ \
+                          name = m.github or (m.name or \"unknown\"); \
+                          listed = builtins.elem m list; \
+                        }}; \
+                      in {{ \
+                        version = get null (pkg.version or null); \
+                        position = get null (pkg.meta.position or null); \
+                        maintainers = get [] (map maintainer (pkg.meta.maintainers or [])); \
+                        has_license = get false (pkg.meta ? license); \
+                        main_program = get null (pkg.meta.mainProgram or null); \
+                        has_update_script = get false (pkg ? updateScript || pkg ? passthru.updateScript); \
+                        has_tests = get false (pkg ? tests || pkg ? passthru.tests); \
+                      }})",
+                    crate::nix_eval_for(&self.pkg)
+                ),
+            ],
+        )
+        .with_context(|| format!("evaluating new package {}", self.pkg))?
+        {
+            Some(info) => serde_json::from_value::<Info>(info)
+                .with_context(|| format!("parsing the evaluation of new package {}", self.pkg))?,
+            None => return Ok(()),
+        };
+        let has_binaries =
+            crate::checks::run_binaries::list_main_binaries(self.outs_dir.path(), "to-check")?
+                .map(|bins| !bins.is_empty())
+                .unwrap_or(false);
+
+        let mut findings = Vec::new();
+        findings.extend(self.placement(&info)?);
+        if info.maintainers.is_empty() {
+            findings.push("❌ has no maintainer".to_string());
+        }
+        for m in info.maintainers.iter().filter(|m| !m.listed) {
+            findings.push(format!(
+                "❌ maintainer `{}` is not in `maintainers/maintainer-list.nix`",
+                m.name
+            ));
+        }
+        if !info.has_license {
+            findings.push("❌ has no license".to_string());
+        }
+        if has_binaries && info.main_program.is_none() {
+            findings.push("❌ has binaries but no `meta.mainProgram`".to_string());
+        }
+        if !info.has_update_script && !info.has_tests {
+            findings.push(
+                "😢 has neither an update script nor `passthru.tests`, consider adding one"
+                    .to_string(),
+            );
+        }
+        findings.push(self.commit(&info));
+        self.findings = Some(findings);
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let findings = match &self.findings {
+            Some(findings) => findings,
+            None => return format!("**new package {}:** 😢 check was interrupted", self.pkg),
+        };
+        let mut res = format!("**new package {}:**\n", self.pkg);
+        for f in findings {
+            res += &format!("  * {}\n", f);
+        }
+        res
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::{anyhow, Context};
+use crossbeam_channel::Receiver;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// A patch, as listed in `patches`
+#[derive(serde::Deserialize)]
+struct Patch {
+    /// The name of the derivation, for fetched patches
+    name: Option<String>,
+    path: PathBuf,
+    drv: Option<String>,
+}
+
+impl Patch {
+    /// Returns a name identifying the patch across versions
+    fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let file_name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if self.path.starts_with("/nix/store") {
+            match file_name.find('-') {
+                Some(i) => file_name[i + 1..].to_string(),
+                None => file_name,
+            }
+        } else {
+            file_name
+        }
+    }
+}
+
+/// How a patch of the to-check version relates to the to-check source
+enum Application {
+    Applies,
+    /// The patch reverse-applies, so upstream probably merged it
+    AlreadyApplied,
+    DoesNotApply,
+}
+
+pub struct Chk {
+    pkg: String,
+    sources: Rc<crate::sources::Shared>,
+    patches_before: Option<Vec<Patch>>,
+    patches_after: Option<Vec<Patch>>,
+    /// Whether the source could be unpacked to test the patches on it
+    has_source: bool,
+    applications: BTreeMap<String, Application>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, sources: Rc<crate::sources::Shared>) -> Chk {
+        Chk {
+            pkg,
+            sources,
+            patches_before: None,
+            patches_after: None,
+            has_source: false,
+            applications: BTreeMap::new(),
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x804abb05417c4e0d8478f10c6e0f0e57),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("patches({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.patches_before = patches(killer, "base", &self.pkg)?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.patches_after = patches(killer, "to-check", &self.pkg)?;
+        let patches = match &self.patches_after {
+            Some(patches) if !patches.is_empty() => patches,
+            _ => return Ok(()),
+        };
+        let source = match self.sources.get(killer, "to-check")? {
+            Some(source) => source,
+            None => return Ok(()),
+        };
+        let tree = match &source.tree {
+            Some(tree) => tree,
+            None => return Ok(()),
+        };
+        self.has_source = true;
+        for patch in patches {
+            // Patches that failed fetching are already reported by the build
+            if !patch.path.is_file() {
+                continue;
+            }
+            let application = if patch_applies(killer, tree, &patch.path, true)? {
+                Application::AlreadyApplied
+            } else if patch_applies(killer, tree, &patch.path, false)? {
+                Application::Applies
+            } else {
+                Application::DoesNotApply
+            };
+            self.applications.insert(patch.name(), application);
+        }
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let (before, after) = match (&self.patches_before, &self.patches_after) {
+            (Some(before), Some(after)) => (before, after),
+            _ => return format!("**patches of {}:** 😢 check was interrupted", self.pkg),
+        };
+        if before.is_empty() && after.is_empty() {
+            return format!("**patches of {}:** ✔ there are no patches", self.pkg);
+        }
+        let names_before = before.iter().map(|p| p.name()).collect::<Vec<_>>();
+        let names_after = after.iter().map(|p| p.name()).collect::<Vec<_>>();
+
+        let mut lines = Vec::new();
+        for name in names_before.iter().filter(|n| !names_after.contains(n)) {
+            lines.push(format!("✔ `{}` was removed", name));
+        }
+        for name in names_after.iter().filter(|n| !names_before.contains(n)) {
+            lines.push(format!("✔ `{}` was added", name));
+        }
+        for name in &names_after {
+            match self.applications.get(name) {
+                Some(Application::AlreadyApplied) if names_before.contains(name) => lines.push(
+                    format!(
+                        "😢 `{}` was kept but reverse-applies on the new source, so upstream probably merged it",
+                        name
+                    ),
+                ),
+                Some(Application::AlreadyApplied) => lines.push(format!(
+                    "😢 `{}` reverse-applies on the source, so upstream probably already has it",
+                    name
+                )),
+                Some(Application::DoesNotApply) => lines.push(format!(
+                    "😢 `{}` does not apply with `-p1` on the new source",
+                    name
+                )),
+                Some(Application::Applies) | None => (),
+            }
+        }
+        if !after.is_empty() && !self.has_source {
+            lines.push("😢 the source could not be unpacked to test the patches".to_string());
+        }
+
+        if lines.is_empty() {
+            return format!(
+                "**patches of {}:** ✔ the {} patches are unchanged and still needed",
+                self.pkg,
+                after.len()
+            );
+        }
+        let mut res = format!("**patches of {}:**\n", self.pkg);
+        for l in lines {
+            res += &format!("  * {}\n", l);
+        }
+        res
+    }
+}
+
+/// Evaluates the patches of the package and realises the fetched ones
+fn patches(killer: &Receiver<()>, version: &str, pkg: &str) -> anyhow::Result<Option<Vec<Patch>>> {
+    let patches = crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(map (p: if builtins.isAttrs p \
+                    then {{ name = p.name or null; path = p.outPath; drv = p.drvPath or null; }} \
+                    else {{ path = toString p; }}) \
+                  ({}.patches or []))",
+                crate::nix_eval_for(pkg)
+            ),
+        ],
+    )
+    .with_context(|| format!("evaluating the patches of {} version of {}", version, pkg))?;
+    let patches = match patches {
+        Some(patches) => serde_json::from_value::<Vec<Patch>>(patches)
+            .with_context(|| format!("parsing the patches of {} version of {}", version, pkg))?,
+        None => return Ok(None),
+    };
+
+    let drvs = patches
+        .iter()
+        .filter_map(|p| p.drv.as_ref())
+        .map(|drv| format!("{}^*", drv))
+        .collect::<Vec<_>>();
+    if !drvs.is_empty() {
+        let mut args = vec!["build", "--no-link"];
+        args.extend(drvs.iter().map(|d| d as &str));
+        if crate::run_nix(killer, false, &args)
+            .with_context(|| format!("fetching the patches of {} version of {}", version, pkg))?
+            .is_none()
+        {
+            return Ok(None);
+        }
+    }
+    Ok(Some(patches))
+}
+
+/// Returns true iff the patch (reverse-)applies cleanly on the tree,
+/// without modifying it
+fn patch_applies(
+    killer: &Receiver<()>,
+    tree: &Path,
+    patch: &Path,
+    reverse: bool,
+) -> anyhow::Result<bool> {
+    let tree_str = tree
+        .to_str()
+        .ok_or_else(|| anyhow!("got non-utf8 source path {:?}", tree))?;
+    let patch_str = patch
+        .to_str()
+        .ok_or_else(|| anyhow!("got non-utf8 patch path {:?}", patch))?;
+    let mut args = vec![
+        "--dry-run",
+        "--batch",
+        "--silent",
+        "-p1",
+        "-d",
+        tree_str,
+        "-i",
+        patch_str,
+    ];
+    if reverse {
+        args.push("--reverse");
+    }
+    Ok(crate::run(killer, true, Path::new("patch"), &args)
+        .with_context(|| format!("trying to apply {:?}", patch))?
+        .map(|out| out.status.success())
+        .unwrap_or(false))
+}
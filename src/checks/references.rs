@@ -0,0 +1,319 @@
+use anyhow::{anyhow, Context};
+use crossbeam_channel::Receiver;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::Read,
+    path::Path,
+    rc::Rc,
+};
+
+/// Maximum number of findings of each kind listed in the report
+const MAX_LISTED: usize = 20;
+
+/// Size of the chunks output files are scanned by
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Length of the paths that are still found when split across two chunks
+const MAX_PATH_LENGTH: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    BuildTimeInput,
+    BuildDirectory,
+    FhsPath,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Finding {
+    kind: Kind,
+    file: String,
+    reference: String,
+}
+
+impl Finding {
+    /// Returns the finding with the store hashes and versions blanked out,
+    /// so that it matches the same finding in another version
+    fn normalised(&self, blank: &impl Fn(&str) -> String) -> Finding {
+        Finding {
+            kind: self.kind,
+            file: blank(&self.file),
+            reference: blank(&self.reference),
+        }
+    }
+}
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    build_inputs_before: Option<HashSet<String>>,
+    build_inputs_after: Option<HashSet<String>>,
+    findings_before: Option<BTreeSet<Finding>>,
+    findings_after: Option<BTreeSet<Finding>>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            build_inputs_before: None,
+            build_inputs_after: None,
+            findings_before: None,
+            findings_after: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0xe804452d40ff4b9ca4c35043f212bbb1),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("references({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.build_inputs_before = build_time_inputs(killer, "base", &self.pkg)?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.build_inputs_after = build_time_inputs(killer, "to-check", &self.pkg)?;
+        if let Some(build_inputs) = &self.build_inputs_before {
+            if !crate::checks::build::outputs(self.outs_dir.path(), "base")?.is_empty() {
+                self.findings_before =
+                    scan_outputs(killer, self.outs_dir.path(), "base", build_inputs)
+                        .with_context(|| format!("scanning the base outputs of {}", self.pkg))?;
+            }
+        }
+        if let Some(build_inputs) = &self.build_inputs_after {
+            self.findings_after =
+                scan_outputs(killer, self.outs_dir.path(), "to-check", build_inputs)
+                    .with_context(|| format!("scanning the to-check outputs of {}", self.pkg))?;
+        }
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let findings_after = match &self.findings_after {
+            Some(f) => f,
+            None => return format!("**references of {}:** 😢 check was interrupted", self.pkg),
+        };
+        if findings_after.is_empty() {
+            return format!("**references of {}:** ✔ no forbidden reference", self.pkg);
+        }
+
+        // Findings are compared regardless of the version bump
+        let blank = crate::version_blanker();
+        let before = self.findings_before.as_ref().map(|before| {
+            before
+                .iter()
+                .map(|f| f.normalised(&blank))
+                .collect::<BTreeSet<_>>()
+        });
+
+        let mut by_kind = BTreeMap::<Kind, Vec<&Finding>>::new();
+        for f in findings_after {
+            by_kind.entry(f.kind).or_default().push(f);
+        }
+        let mut res = format!("**references of {}:**\n", self.pkg);
+        for (kind, findings) in by_kind {
+            res += match kind {
+                Kind::BuildTimeInput => "  * *references to build-time-only dependencies:*\n",
+                Kind::BuildDirectory => "  * *references to the sandbox build directory:*\n",
+                Kind::FhsPath => "  * *references to FHS paths:*\n",
+            };
+            for f in findings.iter().take(MAX_LISTED) {
+                let emoji = match &before {
+                    Some(before) if !before.contains(&f.normalised(&blank)) => "❌ new:",
+                    _ => "😢",
+                };
+                res += &format!(
+                    "    * {} `{}` references `{}`\n",
+                    emoji, f.file, f.reference
+                );
+            }
+            if findings.len() > MAX_LISTED {
+                res += &format!("    * … and {} more\n", findings.len() - MAX_LISTED);
+            }
+        }
+        if self.findings_before.is_none() {
+            res +=
+                "  * *note:* base outputs were not available, so findings could not be compared\n";
+        }
+        res
+    }
+}
+
+/// Returns the store paths that the package only needs at build time,
+/// with their hash stripped
+fn build_time_inputs(
+    killer: &Receiver<()>,
+    version: &str,
+    pkg: &str,
+) -> anyhow::Result<Option<HashSet<String>>> {
+    crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                // New packages do not exist on the base side
+                "(let pkgs = {}; in if !(pkgs ? {}) then [] else let pkg = pkgs.{}; in builtins.filter (p: p != \"\") (map toString ((pkg.nativeBuildInputs or []) ++ [ (pkg.stdenv.cc or null) (pkg.stdenv.cc.cc or null) ])))",
+                crate::nixpkgs(),
+                pkg,
+                pkg,
+            ),
+        ],
+    )
+    .with_context(|| format!("listing the {} build-time inputs of {}", version, pkg))?
+    .map(|paths| {
+        paths
+            .as_array()
+            .and_then(|paths| {
+                paths
+                    .iter()
+                    .map(|p| p.as_str().map(strip_hash))
+                    .collect::<Option<HashSet<String>>>()
+            })
+            .ok_or_else(|| anyhow!("build-time inputs of {} are not a list of strings", pkg))
+    })
+    .transpose()
+}
+
+/// Returns the store path name, without the `/nix/store/<hash>-` prefix
+fn strip_hash(path: &str) -> String {
+    let name = path.strip_prefix("/nix/store/").unwrap_or(path);
+    match name.find('-') {
+        Some(i) => name[i + 1..].to_string(),
+        None => name.to_string(),
+    }
+}
+
+fn scan_outputs(
+    killer: &Receiver<()>,
+    outs_dir: &Path,
+    version: &str,
+    build_inputs: &HashSet<String>,
+) -> anyhow::Result<Option<BTreeSet<Finding>>> {
+    // Paths that appear after something else than a path component are
+    // absolute paths, that should not be there
+    let build_dir =
+        regex::bytes::Regex::new(r"(?-u)(?:^|[^A-Za-z0-9._+\-/])(/build/[A-Za-z0-9._+\-/]*)")
+            .expect("failed compiling the build directory regex");
+    let fhs_path = regex::bytes::Regex::new(
+        r"(?-u)(?:^|[^A-Za-z0-9._+\-/])(/(?:usr/(?:bin|sbin|lib|lib64|libexec|share|local)|bin|sbin|lib|lib64)/[A-Za-z0-9._+\-/]*)",
+    )
+    .expect("failed compiling the FHS path regex");
+
+    // Look at the references nix detected
+    let mut res = BTreeSet::new();
+    for (output, link) in crate::checks::build::outputs(outs_dir, version)? {
+        let root = std::fs::canonicalize(&link)
+            .with_context(|| format!("resolving the store path of {:?}", link))?;
+        let root_str = root
+            .to_str()
+            .ok_or_else(|| anyhow!("got non-utf8 store path {:?}", root))?;
+        let references = match crate::nix(killer, &["path-info", "--json", root_str])
+            .with_context(|| format!("listing the references of {:?}", root))?
+        {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let info = references
+            .get(0)
+            .or_else(|| references.get(root_str))
+            .ok_or_else(|| anyhow!("nix path-info did not return info about {:?}", root))?;
+        let references = info
+            .get("references")
+            .and_then(|r| r.as_array())
+            .ok_or_else(|| anyhow!("nix path-info did not return references for {:?}", root))?;
+        for r in references {
+            let r = strip_hash(
+                r.as_str()
+                    .ok_or_else(|| anyhow!("nix path-info returned a non-string reference"))?,
+            );
+            if build_inputs.contains(&r) {
+                res.insert(Finding {
+                    kind: Kind::BuildTimeInput,
+                    file: format!("${}", output),
+                    reference: r,
+                });
+            }
+        }
+    }
+
+    // And scan the files for absolute paths
+    for (file, f) in crate::checks::build::output_files(outs_dir, version)? {
+        let is_file = std::fs::symlink_metadata(&f)
+            .with_context(|| format!("reading the metadata of {:?}", f))?
+            .is_file();
+        if !is_file {
+            continue;
+        }
+        let regexes = [
+            (Kind::BuildDirectory, &build_dir),
+            (Kind::FhsPath, &fhs_path),
+        ];
+        for (kind, reference) in scan_file(&f, &regexes)? {
+            res.insert(Finding {
+                kind,
+                file: file.clone(),
+                reference,
+            });
+        }
+    }
+    Ok(Some(res))
+}
+
+/// Returns the paths matched by the regexes in the file, reading it by
+/// chunks not to load large outputs in memory
+///
+/// The regexes capture the path in their first group, after a character
+/// that ends any path, or the start of the file.
+fn scan_file(
+    path: &Path,
+    regexes: &[(Kind, &regex::bytes::Regex)],
+) -> anyhow::Result<BTreeSet<(Kind, String)>> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening {:?}", path))?;
+    let mut res = BTreeSet::new();
+    let mut buf = Vec::new();
+    // Where the paths reported from this chunk start: after the end of the
+    // previous chunk, whose last character is kept for context
+    let mut start = 0;
+    loop {
+        let read = (&mut file)
+            .take(CHUNK_SIZE)
+            .read_to_end(&mut buf)
+            .with_context(|| format!("reading {:?}", path))?;
+        let is_last = (read as u64) < CHUNK_SIZE;
+        // Paths starting near the end could go on in the next chunk, so
+        // leave them for when the next chunk is scanned
+        let end = if is_last {
+            buf.len()
+        } else {
+            buf.len() - MAX_PATH_LENGTH
+        };
+        for (kind, regex) in regexes {
+            for c in regex.captures_iter(&buf) {
+                let m = c.get(1).expect("regex has no first group");
+                if m.start() >= start && m.start() < end {
+                    res.insert((*kind, String::from_utf8_lossy(m.as_bytes()).into_owned()));
+                }
+            }
+        }
+        if is_last {
+            return Ok(res);
+        }
+        buf.drain(..end - 1);
+        start = 1;
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Where nixpkgs and NixOS keep their release notes
+const RELEASE_NOTES_DIRS: &[&str] = &["doc/release-notes", "nixos/doc/manual/release-notes"];
+
+/// How many remaining references to list in the report
+const MAX_REFERENCES: usize = 50;
+
+/// What is left of the attribute in the to-check version
+enum Leftover {
+    Nothing,
+    Alias,
+    Throw,
+}
+
+struct Removal {
+    leftover: Leftover,
+    /// The release notes that gained mentions of the package
+    release_notes: Vec<PathBuf>,
+    /// Whether all the packages still evaluate without aliases
+    evaluates: bool,
+    /// The `file:line` of the lines still mentioning the package
+    references: Vec<String>,
+}
+
+pub struct Chk {
+    pkg: String,
+    notes_before: Option<BTreeMap<PathBuf, usize>>,
+    removal: Option<Removal>,
+}
+
+impl Chk {
+    pub fn new(pkg: String) -> Chk {
+        Chk {
+            pkg,
+            notes_before: None,
+            removal: None,
+        }
+    }
+
+    /// The name the package is referred to by in nix files, eg. `foo`
+    /// for `python3Packages.foo`
+    fn short_name(&self) -> &str {
+        self.pkg.rsplit('.').next().unwrap_or(&self.pkg)
+    }
+
+    /// Counts the mentions of the package in each release notes file
+    fn release_notes(&self) -> anyhow::Result<BTreeMap<PathBuf, usize>> {
+        let mut res = BTreeMap::new();
+        for dir in RELEASE_NOTES_DIRS.iter().map(Path::new) {
+            if !dir.is_dir() {
+                continue;
+            }
+            for f in crate::walk_dir(dir)? {
+                if f.is_dir() {
+                    continue;
+                }
+                let contents =
+                    std::fs::read(&f).with_context(|| format!("reading release notes {:?}", f))?;
+                let mentions = String::from_utf8_lossy(&contents)
+                    .lines()
+                    .filter(|l| mentions(l, self.short_name()))
+                    .count();
+                res.insert(f, mentions);
+            }
+        }
+        Ok(res)
+    }
+
+    fn leftover(&self, killer: &Receiver<()>) -> anyhow::Result<Option<Leftover>> {
+        let alias = crate::nix(
+            killer,
+            &[
+                "eval",
+                "--json",
+                &format!(
+                    "(let \
+                        pkgs = {}; \
+                        res = builtins.tryEval pkgs.{}; \
+                      in if !(pkgs ? {}) then \"none\" \
+                      else if res.success then \"alias\" \
+                      else \"throw\")",
+                    crate::nixpkgs(),
+                    self.pkg,
+                    self.pkg
+                ),
+            ],
+        )
+        .with_context(|| format!("evaluating the alias for {}", self.pkg))?;
+        Ok(alias.map(|alias| match alias.as_str() {
+            Some("alias") => Leftover::Alias,
+            Some("throw") => Leftover::Throw,
+            _ => Leftover::Nothing,
+        }))
+    }
+
+    /// Lists the lines of nix files, aliases excepted, that still mention
+    /// the package
+    fn references(&self) -> anyhow::Result<Vec<String>> {
+        let mut res = Vec::new();
+        for f in crate::sources::files(Path::new("."))? {
+            let is_aliases = f
+                .file_name()
+                .map(|n| n.to_string_lossy().ends_with("aliases.nix"))
+                .unwrap_or(false);
+            if f.extension().map(|e| e != "nix").unwrap_or(true) || is_aliases {
+                continue;
+            }
+            let contents =
+                std::fs::read(&f).with_context(|| format!("reading nix file {:?}", f))?;
+            for (i, line) in String::from_utf8_lossy(&contents).lines().enumerate() {
+                if mentions(line, self.short_name()) {
+                    res.push(format!("{}:{}", f.display(), i + 1));
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x28bbc0ccc43642adadef2c1d4c1431ba),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("removed-package({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        self.notes_before = Some(self.release_notes()?);
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        let notes_before = match &self.notes_before {
+            Some(notes) => notes,
+            None => return Ok(()),
+        };
+        let release_notes = self
+            .release_notes()?
+            .into_iter()
+            .filter(|(f, n)| *n > notes_before.get(f).copied().unwrap_or(0))
+            .map(|(f, _)| f)
+            .collect();
+        let leftover = match self.leftover(killer)? {
+            Some(leftover) => leftover,
+            None => return Ok(()),
+        };
+        // Like the nixpkgs CI, make sure nothing relies on the package
+        // through an alias, asking for the derivations so that the
+        // dependencies of each package get evaluated too
+        let mut child = std::process::Command::new("nix-env")
+            .args([
+                "-f",
+                ".",
+                "-qa",
+                "--drv-path",
+                "--arg",
+                "config",
+                "{ allowAliases = false; }",
+                "--arg",
+                "overlays",
+                "[]",
+            ])
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .context("spawning nix-env to evaluate all packages")?;
+        let evaluates = match crate::wait_killable(killer, &mut child)? {
+            Some(status) => status.success(),
+            None => return Ok(()),
+        };
+        let references = self
+            .references()
+            .with_context(|| format!("searching for references to {}", self.pkg))?;
+        self.removal = Some(Removal {
+            leftover,
+            release_notes,
+            evaluates,
+            references,
+        });
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let removal = match &self.removal {
+            Some(removal) => removal,
+            None => return format!("**removal of {}:** 😢 check was interrupted", self.pkg),
+        };
+        let mut res = format!("**removal of {}:**\n", self.pkg);
+        res += match removal.leftover {
+            Leftover::Alias => "  * ✔ an alias replaces it\n",
+            Leftover::Throw => "  * ✔ an alias throws an explanation\n",
+            Leftover::Nothing => {
+                "  * ❌ **no alias or `throw` was added to `pkgs/top-level/aliases.nix`**\n"
+            }
+        };
+        if removal.release_notes.is_empty() {
+            res += "  * 😢 not mentioned in the release notes\n";
+        } else {
+            res += &format!(
+                "  * ✔ mentioned in the release notes: {}\n",
+                removal
+                    .release_notes
+                    .iter()
+                    .map(|f| format!("`{}`", f.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        if removal.evaluates {
+            res += "  * ✔ all packages still evaluate without aliases\n";
+        } else {
+            res += "  * ❌ **evaluating all packages without aliases fails**, see the output of `nix-env -qa`\n";
+        }
+        if removal.references.is_empty() {
+            res += &format!(
+                "  * ✔ no nix file mentions `{}` any longer\n",
+                self.short_name()
+            );
+            return res;
+        }
+        res += &format!(
+            "  * 😢 {} lines of nix files still mention `{}`\n",
+            removal.references.len(),
+            self.short_name()
+        );
+        res += "\n<details><summary>remaining mentions</summary>\n\n";
+        for r in removal.references.iter().take(MAX_REFERENCES) {
+            res += &format!("  * `{}`\n", r);
+        }
+        if removal.references.len() > MAX_REFERENCES {
+            res += &format!(
+                "  * … and {} more\n",
+                removal.references.len() - MAX_REFERENCES
+            );
+        }
+        res += "\n</details>\n";
+        res
+    }
+}
+
+/// Returns whether the line contains the name as a whole nix identifier
+fn mentions(line: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '\'';
+    line.match_indices(name).any(|(i, _)| {
+        !line[..i].chars().next_back().map(is_ident).unwrap_or(false)
+            && !line[i + name.len()..]
+                .chars()
+                .next()
+                .map(is_ident)
+                .unwrap_or(false)
+    })
+}
@@ -0,0 +1,219 @@
+use anyhow::{anyhow, Context};
+use crossbeam_channel::Receiver;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Maximum number of differing files listed in the report
+const MAX_LISTED: usize = 50;
+
+/// Maximum number of lines of the diffoscope output put in the report
+const MAX_DIFFOSCOPE_LINES: usize = 100;
+
+enum Outcome {
+    Reproducible,
+    RebuildFailed,
+    NotReproducible {
+        differing_files: Vec<String>,
+        diffoscope: Option<String>,
+    },
+}
+
+pub struct Chk {
+    pkg: String,
+    outs_dir: Rc<tempfile::TempDir>,
+    result: Option<Outcome>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+        Chk {
+            pkg,
+            outs_dir,
+            result: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x3ee95a6aea3a4c36a333b447592d4aeb),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("reproducibility({})", self.pkg)
+    }
+
+    fn run_before(&mut self, _: &Receiver<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        let rebuild = crate::run_nix(
+            killer,
+            false,
+            &[
+                "build",
+                "--rebuild",
+                "--keep-failed",
+                "--no-link",
+                &crate::nix_eval_for(&self.pkg),
+            ],
+        )
+        .with_context(|| format!("rebuilding the to-check version of package {}", self.pkg))?;
+        let rebuild = match rebuild {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        if rebuild.status.success() {
+            self.result = Some(Outcome::Reproducible);
+            return Ok(());
+        }
+
+        // nix keeps the differing outputs as `<output>.check`
+        let mut differing_files = Vec::new();
+        let mut diffoscope = String::new();
+        for (output, link) in crate::checks::build::outputs(self.outs_dir.path(), "to-check")? {
+            let path = std::fs::canonicalize(&link)
+                .with_context(|| format!("resolving the store path of {:?}", link))?;
+            let check_path = PathBuf::from(format!("{}.check", path.display()));
+            if !check_path.exists() {
+                continue;
+            }
+            differing_files.extend(
+                differing_files_between(&path, &check_path)
+                    .with_context(|| format!("comparing {:?} with {:?}", path, check_path))?
+                    .into_iter()
+                    .map(|f| format!("${}{}", output, f)),
+            );
+            if is_in_path("diffoscope") {
+                if let Some(out) = crate::run(
+                    killer,
+                    true,
+                    Path::new("diffoscope"),
+                    &[
+                        "--text",
+                        "-",
+                        path.to_str()
+                            .ok_or_else(|| anyhow!("got non-utf8 store path {:?}", path))?,
+                        check_path
+                            .to_str()
+                            .ok_or_else(|| anyhow!("got non-utf8 store path {:?}", check_path))?,
+                    ],
+                )
+                .context("running diffoscope")?
+                {
+                    diffoscope += &String::from_utf8_lossy(&out.stdout);
+                }
+            }
+        }
+
+        self.result = Some(if differing_files.is_empty() {
+            Outcome::RebuildFailed
+        } else {
+            Outcome::NotReproducible {
+                differing_files,
+                diffoscope: if diffoscope.is_empty() {
+                    None
+                } else {
+                    Some(diffoscope)
+                },
+            }
+        });
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        match &self.result {
+            None => format!("**reproducibility of {}:** 😢 check was interrupted", self.pkg),
+            Some(Outcome::Reproducible) => {
+                format!("**reproducibility of {}:** ✔ rebuilt identically", self.pkg)
+            }
+            Some(Outcome::RebuildFailed) => format!(
+                "**reproducibility of {}:** 😢 the rebuild failed without producing outputs to compare",
+                self.pkg
+            ),
+            Some(Outcome::NotReproducible {
+                differing_files,
+                diffoscope,
+            }) => {
+                let mut res = format!(
+                    "**reproducibility of {}:** 😢 the rebuild produced different outputs\n",
+                    self.pkg
+                );
+                for f in differing_files.iter().take(MAX_LISTED) {
+                    res += &format!("  * `{}` differs\n", f);
+                }
+                if differing_files.len() > MAX_LISTED {
+                    res += &format!("  * … and {} more\n", differing_files.len() - MAX_LISTED);
+                }
+                if let Some(diffoscope) = diffoscope {
+                    let lines = diffoscope.lines().collect::<Vec<_>>();
+                    res += "\n<details><summary>diffoscope summary</summary>\n\n```\n";
+                    for l in lines.iter().take(MAX_DIFFOSCOPE_LINES) {
+                        res += l;
+                        res += "\n";
+                    }
+                    if lines.len() > MAX_DIFFOSCOPE_LINES {
+                        res += &format!("[… {} more lines]\n", lines.len() - MAX_DIFFOSCOPE_LINES);
+                    }
+                    res += "```\n\n</details>\n";
+                }
+                res
+            }
+        }
+    }
+}
+
+/// Returns the paths (relative to the roots, with a leading `/`, or the
+/// empty string for the roots themselves) that differ between `a` and `b`
+fn differing_files_between(a: &Path, b: &Path) -> anyhow::Result<Vec<String>> {
+    let list = |root: &Path| -> anyhow::Result<BTreeSet<PathBuf>> {
+        let mut res = BTreeSet::new();
+        res.insert(PathBuf::new());
+        if root.is_dir() {
+            for f in crate::walk_dir(root)? {
+                res.insert(
+                    f.strip_prefix(root)
+                        .expect("walked file is not in walked directory")
+                        .to_path_buf(),
+                );
+            }
+        }
+        Ok(res)
+    };
+    let files_a = list(a)?;
+    let files_b = list(b)?;
+
+    let mut res = Vec::new();
+    for f in files_a.union(&files_b) {
+        let differs = if files_a.contains(f) && files_b.contains(f) {
+            !crate::same_file(&a.join(f), &b.join(f))?
+        } else {
+            true
+        };
+        if differs {
+            if f.as_os_str().is_empty() {
+                res.push(String::new());
+            } else {
+                res.push(format!("/{}", f.display()));
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn is_in_path(bin: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|d| d.join(bin).is_file()))
+        .unwrap_or(false)
+}
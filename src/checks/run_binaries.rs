@@ -2,27 +2,42 @@ use anyhow::{anyhow, Context};
 use crossbeam_channel::Receiver;
 use std::{
     collections::{HashMap, HashSet},
-    path::Path,
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
+/// Arguments with which a binary is not expected to touch the filesystem
+const INFORMATIVE_ARGS: &[&str] = &["--version", "--help", "-h", "-V"];
+
+/// Files that a binary left behind when run with informative arguments
+struct StateWrite {
+    version: String,
+    bin: String,
+    args: String,
+    files: Vec<String>,
+}
+
 pub struct Chk {
     pkg: String,
     outs_dir: Rc<tempfile::TempDir>,
+    options: Rc<crate::Options>,
 
     new_bins: HashMap<String, Option<bool>>,
     updated_bins: HashMap<String, Option<(bool, bool)>>,
     removed_bins: HashSet<String>,
+    state_writes: Vec<StateWrite>,
 }
 
 impl Chk {
-    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>) -> Chk {
+    pub fn new(pkg: String, outs_dir: Rc<tempfile::TempDir>, options: Rc<crate::Options>) -> Chk {
         Chk {
             pkg,
             outs_dir,
+            options,
             new_bins: HashMap::new(),
             updated_bins: HashMap::new(),
             removed_bins: HashSet::new(),
+            state_writes: Vec::new(),
         }
     }
 }
@@ -45,32 +60,15 @@ impl crate::Check for Chk {
 
     fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
         // List the binaries
-        let bin_dir = match std::fs::read_dir(self.outs_dir.path().join("base").join("bin")) {
-            Ok(d) => d,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
-            Err(e) => return Err(e).context(format!("listing the base binaries for {}", self.pkg)),
-        };
-        let base_bins = bin_dir
-            .collect::<std::io::Result<Vec<_>>>()
+        let base_bins = match list_binaries(self.outs_dir.path(), "base")
             .with_context(|| format!("listing the base binaries for {}", self.pkg))?
-            .into_iter()
-            .map(|f| f.file_name().to_str().map(|s| s.to_string()))
-            .collect::<Option<Vec<String>>>()
-            .ok_or_else(|| anyhow!("a base binary for {} had a non-utf8 name"))?
-            .into_iter()
-            .filter(|f| !(f.starts_with(".") && f.ends_with("-wrapped")))
-            .collect::<HashSet<String>>();
-        let to_check_bins = std::fs::read_dir(self.outs_dir.path().join("to-check").join("bin"))
-            .with_context(|| format!("listing the to-check binaries for {}", self.pkg))?
-            .collect::<std::io::Result<Vec<_>>>()
+        {
+            Some(bins) => bins,
+            None => return Ok(()),
+        };
+        let to_check_bins = list_binaries(self.outs_dir.path(), "to-check")
             .with_context(|| format!("listing the to-check binaries for {}", self.pkg))?
-            .into_iter()
-            .map(|f| f.file_name().to_str().map(|s| s.to_string()))
-            .collect::<Option<Vec<String>>>()
-            .ok_or_else(|| anyhow!("a to-check binary for {} had a non-utf8 name"))?
-            .into_iter()
-            .filter(|f| !(f.starts_with(".") && f.ends_with("-wrapped")))
-            .collect::<HashSet<String>>();
+            .unwrap_or_default();
 
         // Figure out which binaries to run
         let choices = to_check_bins.iter().cloned().collect::<Vec<_>>();
@@ -89,6 +87,7 @@ impl crate::Check for Chk {
             .collect();
 
         // Run and fill in the results
+        let mut state_writes = Vec::new();
         self.removed_bins = base_bins.difference(&to_check_bins).cloned().collect();
         self.new_bins = to_check_bins
             .difference(&base_bins)
@@ -102,6 +101,8 @@ impl crate::Check for Chk {
                             "base",
                             &bin,
                             &self.pkg,
+                            self.options.sandbox_binaries,
+                            &mut state_writes,
                         )?)
                     } else {
                         None
@@ -116,8 +117,24 @@ impl crate::Check for Chk {
                     bin.clone(),
                     if chosen[bin] {
                         Some((
-                            run_binary(killer, self.outs_dir.path(), "base", &bin, &self.pkg)?,
-                            run_binary(killer, self.outs_dir.path(), "to-check", &bin, &self.pkg)?,
+                            run_binary(
+                                killer,
+                                self.outs_dir.path(),
+                                "base",
+                                &bin,
+                                &self.pkg,
+                                self.options.sandbox_binaries,
+                                &mut state_writes,
+                            )?,
+                            run_binary(
+                                killer,
+                                self.outs_dir.path(),
+                                "to-check",
+                                &bin,
+                                &self.pkg,
+                                self.options.sandbox_binaries,
+                                &mut state_writes,
+                            )?,
                         ))
                     } else {
                         None
@@ -125,6 +142,7 @@ impl crate::Check for Chk {
                 ))
             })
             .collect::<anyhow::Result<_>>()?;
+        self.state_writes = state_writes;
 
         Ok(())
     }
@@ -134,11 +152,19 @@ impl crate::Check for Chk {
     }
 
     fn report(&self) -> String {
-        if self.new_bins.is_empty() && self.updated_bins.is_empty() && self.removed_bins.is_empty()
+        if self.new_bins.is_empty()
+            && self.updated_bins.is_empty()
+            && self.removed_bins.is_empty()
+            && self.state_writes.is_empty()
         {
             return String::new();
         }
         let mut res = format!("**binaries of {}:**\n", self.pkg);
+        let any_run = self.new_bins.values().any(|t| t.is_some())
+            || self.updated_bins.values().any(|t| t.is_some());
+        if self.options.sandbox_binaries && any_run {
+            res += "  * *note:* binaries were run in a sandbox, without network access, with a throwaway home directory and a read-only store\n";
+        }
         if !self.removed_bins.is_empty() {
             res += &format!("  * *removed binaries:* 😢 {:?}\n", self.removed_bins);
         }
@@ -170,20 +196,85 @@ impl crate::Check for Chk {
             }
             res += "\n";
         }
+        if !self.state_writes.is_empty() {
+            res += "  * *binaries writing files when only asked for information:*\n";
+            for w in &self.state_writes {
+                res += &format!(
+                    "    * 😢 {} version of `{} {}` wrote {}\n",
+                    w.version,
+                    w.bin,
+                    w.args,
+                    w.files
+                        .iter()
+                        .map(|f| format!("`{}`", f))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            }
+            res += "\n";
+        }
         res
     }
 }
 
+/// Lists the binaries in the `bin` directory of the output built for
+/// `version`, or returns `None` if there is no such directory
+pub fn list_binaries(outs_dir: &Path, version: &str) -> anyhow::Result<Option<HashSet<String>>> {
+    binaries_in(&outs_dir.join(version).join("bin"), version)
+}
+
+/// Lists the binaries `lib.getExe` looks for the main program in, those
+/// of the `bin` output if the package has one and of `out` otherwise
+pub fn list_main_binaries(
+    outs_dir: &Path,
+    version: &str,
+) -> anyhow::Result<Option<HashSet<String>>> {
+    let outputs = crate::checks::build::outputs(outs_dir, version)?;
+    let output = outputs
+        .iter()
+        .find(|(o, _)| o == "bin")
+        .or_else(|| outputs.iter().find(|(o, _)| o == "out"));
+    match output {
+        Some((_, link)) => binaries_in(&link.join("bin"), version),
+        None => Ok(None),
+    }
+}
+
+fn binaries_in(bin_dir: &Path, version: &str) -> anyhow::Result<Option<HashSet<String>>> {
+    let bin_dir = match std::fs::read_dir(bin_dir) {
+        Ok(d) => d,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("listing the binaries"),
+    };
+    Ok(Some(
+        bin_dir
+            .collect::<std::io::Result<Vec<_>>>()
+            .context("listing the binaries")?
+            .into_iter()
+            .map(|f| f.file_name().to_str().map(|s| s.to_string()))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| anyhow!("a {} binary had a non-utf8 name", version))?
+            .into_iter()
+            .filter(|f| !(f.starts_with(".") && f.ends_with("-wrapped")))
+            .collect::<HashSet<String>>(),
+    ))
+}
+
 /// Returns true iff the binary was run successfully
+///
+/// Each run gets fresh home, XDG and temporary directories. Files left
+/// there by runs with informative arguments get pushed to `state_writes`.
 fn run_binary(
     killer: &Receiver<()>,
     outs_dir: &Path,
     version: &str,
     bin: &str,
     pkg: &str,
+    sandbox: bool,
+    state_writes: &mut Vec<StateWrite>,
 ) -> anyhow::Result<bool> {
     let theme = crate::theme();
-    let mut try_arguments = vec!["--version", "--help", "-h", "-V"].into_iter().fuse();
+    let mut try_arguments = INFORMATIVE_ARGS.iter().cloned().fuse();
     loop {
         // Ask the user which arguments to pass
         let mut args = dialoguer::Input::with_theme(&*theme);
@@ -195,19 +286,49 @@ fn run_binary(
         if let Some(arg) = try_arguments.next() {
             args.with_initial_text(arg);
         }
-        let args: String = args
+        let args_str: String = args
             .interact_text()
             .context("asking the user what parameters to pass")?;
-        let args = args.split(" ").collect::<Vec<_>>();
+        let args = args_str.split(" ").collect::<Vec<_>>();
+
+        // Prepare a pristine environment for the run
+        let run_dir =
+            tempfile::tempdir().context("creating temporary directory for running the binary")?;
+        let home = run_dir.path().join("home");
+        let tmp = run_dir.path().join("tmp");
+        for d in &[&home, &tmp] {
+            std::fs::create_dir(d).with_context(|| format!("creating directory {:?}", d))?;
+        }
 
         // Run the binary
-        crate::run(
-            killer,
-            false,
-            &outs_dir.join(version).join("bin").join(bin),
-            &args,
-        )
-        .with_context(|| format!("running binary {} of package {}", bin, pkg))?;
+        let bin_path = outs_dir.join(version).join("bin").join(bin);
+        let process = if sandbox {
+            sandboxed_command(&bin_path, &args, &home, &tmp)
+                .with_context(|| format!("preparing the sandbox for binary {}", bin))?
+        } else {
+            let mut process = std::process::Command::new(&bin_path);
+            process
+                .args(&args)
+                .envs(run_env(&home, &tmp))
+                .current_dir(&home);
+            process
+        };
+        crate::run_command(killer, false, process)
+            .with_context(|| format!("running binary {} of package {}", bin, pkg))?;
+
+        // Look for the files it left behind
+        if INFORMATIVE_ARGS.contains(&args_str.trim()) {
+            let files = left_behind(&home, &tmp)
+                .with_context(|| format!("listing the files written by binary {}", bin))?;
+            if !files.is_empty() {
+                state_writes.push(StateWrite {
+                    version: version.to_string(),
+                    bin: bin.to_string(),
+                    args: args_str.trim().to_string(),
+                    files,
+                });
+            }
+        }
 
         // Ask the user whether the run was a success
         let res = dialoguer::Select::with_theme(&*theme)
@@ -225,3 +346,79 @@ fn run_binary(
         }
     }
 }
+
+/// Returns the environment variables pointing the binary to its fresh
+/// home, XDG and temporary directories
+fn run_env(home: &Path, tmp: &Path) -> Vec<(&'static str, PathBuf)> {
+    vec![
+        ("HOME", home.to_path_buf()),
+        ("XDG_CONFIG_HOME", home.join(".config")),
+        ("XDG_CACHE_HOME", home.join(".cache")),
+        ("XDG_DATA_HOME", home.join(".local/share")),
+        ("XDG_STATE_HOME", home.join(".local/state")),
+        ("TMPDIR", tmp.to_path_buf()),
+    ]
+}
+
+/// Lists the files and empty directories that a run left in its home
+/// and temporary directories
+fn left_behind(home: &Path, tmp: &Path) -> anyhow::Result<Vec<String>> {
+    let mut res = Vec::new();
+    for (dir, display) in &[(home, "~"), (tmp, "$TMPDIR")] {
+        for f in crate::walk_dir(dir)? {
+            let is_dir = std::fs::symlink_metadata(&f)
+                .with_context(|| format!("reading the metadata of {:?}", f))?
+                .is_dir();
+            let is_empty = || -> anyhow::Result<bool> {
+                Ok(std::fs::read_dir(&f)
+                    .with_context(|| format!("listing directory {:?}", f))?
+                    .next()
+                    .is_none())
+            };
+            if !is_dir || is_empty()? {
+                let rel = f
+                    .strip_prefix(dir)
+                    .expect("walked file is not in walked directory");
+                res.push(format!("{}/{}", display, rel.display()));
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Where the throwaway home directory is mounted in the sandbox, out of
+/// `/tmp` so that mounting the temporary directory does not hide it
+const SANDBOX_HOME: &str = "/home/user";
+
+/// Returns a command running the binary inside a bubblewrap sandbox,
+/// with no network access, a read-only nix store and a throwaway home
+/// directory
+fn sandboxed_command(
+    bin: &Path,
+    args: &[&str],
+    home: &Path,
+    tmp: &Path,
+) -> anyhow::Result<std::process::Command> {
+    // The out-link lives outside of the store, so resolve it to the
+    // store path that will actually be visible from the sandbox
+    let bin = std::fs::canonicalize(bin)
+        .with_context(|| format!("resolving the store path of binary {:?}", bin))?;
+
+    let mut process = std::process::Command::new("bwrap");
+    process
+        .args(["--unshare-all", "--die-with-parent"])
+        .args(["--ro-bind", "/nix/store", "/nix/store"])
+        .args(["--proc", "/proc", "--dev", "/dev"])
+        .arg("--bind")
+        .arg(home)
+        .arg(SANDBOX_HOME)
+        .arg("--bind")
+        .arg(tmp)
+        .arg("/tmp")
+        .args(["--chdir", SANDBOX_HOME]);
+    for (var, value) in run_env(Path::new(SANDBOX_HOME), Path::new("/tmp")) {
+        process.arg("--setenv").arg(var).arg(value);
+    }
+    process.arg("--").arg(&bin).args(args);
+    Ok(process)
+}
@@ -1,21 +1,104 @@
 use anyhow::Context;
 use crossbeam_channel::Receiver;
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::Path,
+    rc::Rc,
+};
+
+/// Depth after which nested sets of tests are no longer walked, to avoid
+/// walking eg. the whole set of NixOS tests
+const MAX_DEPTH: usize = 4;
+
+/// Maps the names of the failed tests to the log of their failed
+/// derivation, or to `None` if the failure could not be attributed
+type FailureLogs = BTreeMap<String, Option<crate::build_log::FailureLog>>;
+
+/// Results of a test that was run several times, including the initial run
+#[derive(Clone, Copy)]
+struct Runs {
+    passed: u32,
+    total: u32,
+}
+
+impl Runs {
+    fn always_passed(&self) -> bool {
+        self.passed == self.total
+    }
+
+    fn never_passed(&self) -> bool {
+        self.passed == 0
+    }
+
+    fn is_flaky(&self) -> bool {
+        !self.always_passed() && !self.never_passed()
+    }
+}
 
 pub struct Chk {
     pkg: String,
+    options: Rc<crate::Options>,
+    batch: Rc<Batch>,
     builds_before: HashMap<String, Option<bool>>,
     builds_after: HashMap<String, Option<bool>>,
+    failure_logs_after: FailureLogs,
+    reruns_before: HashMap<String, Runs>,
+    reruns_after: HashMap<String, Runs>,
+    skipped_after: Vec<(String, String)>,
 }
 
 impl Chk {
-    pub fn new(pkg: String) -> Chk {
+    pub fn new(pkg: String, options: Rc<crate::Options>, batch: Rc<Batch>) -> Chk {
+        batch.register(&pkg);
         Chk {
             pkg,
+            options,
+            batch,
             builds_before: HashMap::new(),
             builds_after: HashMap::new(),
+            failure_logs_after: FailureLogs::new(),
+            reruns_before: HashMap::new(),
+            reruns_after: HashMap::new(),
+            skipped_after: Vec::new(),
         }
     }
+
+    /// Describes the outcome of a test that was rerun, if it was
+    fn rerun_summary(&self, test: &str) -> Option<String> {
+        let after = self.reruns_after.get(test)?;
+        let before = self.reruns_before.get(test);
+        let passed_before = self.builds_before.get(test).copied().flatten();
+        Some(if after.is_flaky() {
+            format!(
+                "😢 {} is flaky, it passed {} out of {} runs",
+                test, after.passed, after.total
+            )
+        } else if after.never_passed() {
+            match (passed_before, before) {
+                (_, Some(before)) if before.is_flaky() => format!(
+                    "❌ {} went from flaky to failing all {} runs",
+                    test, after.total
+                ),
+                (Some(true), _) => {
+                    format!("❌ {} started failing, in all {} runs", test, after.total)
+                }
+                _ => format!(
+                    "😢 {} fails consistently, in all {} runs",
+                    test, after.total
+                ),
+            }
+        } else {
+            match before {
+                Some(before) if before.is_flaky() => format!(
+                    "💚 {} is no longer flaky, it passed all {} runs",
+                    test, after.total
+                ),
+                Some(_) => format!("💚 {} was fixed, it passed all {} runs", test, after.total),
+                None => return None,
+            }
+        })
+    }
 }
 
 impl crate::Check for Chk {
@@ -31,12 +114,44 @@ impl crate::Check for Chk {
     }
 
     fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
-        self.builds_before = build(killer, "base", &self.pkg)?;
+        self.builds_before = self
+            .batch
+            .take(killer, &self.options.run_dir, "base", &self.pkg)?
+            .results;
+        let failing = self
+            .builds_before
+            .iter()
+            .filter(|(_, res)| **res == Some(false))
+            .map(|(test, _)| (test.clone(), false))
+            .collect::<Vec<_>>();
+        self.reruns_before = rerun(killer, self.options.rerun_tests, "base", &self.pkg, failing)?;
         Ok(())
     }
 
     fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
-        self.builds_after = build(killer, "to-check", &self.pkg)?;
+        let built = self
+            .batch
+            .take(killer, &self.options.run_dir, "to-check", &self.pkg)?;
+        self.builds_after = built.results;
+        self.failure_logs_after = built.failure_logs;
+        self.skipped_after = built.skipped;
+        let to_rerun = self
+            .builds_after
+            .iter()
+            .filter(|(test, res)| {
+                **res == Some(false)
+                    || (**res == Some(true)
+                        && self.builds_before.get(*test).copied().flatten() == Some(false))
+            })
+            .map(|(test, res)| (test.clone(), *res == Some(true)))
+            .collect::<Vec<_>>();
+        self.reruns_after = rerun(
+            killer,
+            self.options.rerun_tests,
+            "to-check",
+            &self.pkg,
+            to_rerun,
+        )?;
         Ok(())
     }
 
@@ -82,6 +197,10 @@ impl crate::Check for Chk {
         if !new_tests.is_empty() {
             res += "  * *added tests:*\n";
             for (test, result) in &new_tests {
+                if let Some(summary) = self.rerun_summary(test) {
+                    res += &format!("    * {}\n", summary);
+                    continue;
+                }
                 match result {
                     None => res += &format!("    * 😢 {} was interrupted\n", test),
                     Some(true) => res += &format!("    * 💚 {} was run successfully\n", test),
@@ -93,6 +212,10 @@ impl crate::Check for Chk {
         if !updated_tests.is_empty() {
             res += "  * *updated tests:*\n";
             for (test, result) in &updated_tests {
+                if let Some(summary) = self.rerun_summary(test) {
+                    res += &format!("    * {}\n", summary);
+                    continue;
+                }
                 match result {
                     (None, None) => res += &format!("    * 😢 {} was interrupted twice\n", test),
                     (None, Some(after)) => {
@@ -125,59 +248,362 @@ impl crate::Check for Chk {
             }
             res += "\n";
         }
+        if !self.skipped_after.is_empty() {
+            res += "  * *skipped values that are not tests:*\n";
+            for (name, kind) in &self.skipped_after {
+                res += &format!("    * {} (a {})\n", name, kind);
+            }
+            res += "\n";
+        }
+        for (test, log) in &self.failure_logs_after {
+            match log {
+                Some(log) => {
+                    res += &format!("*failure of test {}:*\n", test);
+                    res += &log.report();
+                }
+                None => res += &format!("*failure of test {}:* a dependency failed\n", test),
+            }
+            res += "\n";
+        }
         res
     }
 }
 
-/// Returns true iff the build was successful
+/// The outcome of building all the tests of a package
+#[derive(Default)]
+struct Built {
+    /// Whether the build was successful, for each test
+    results: HashMap<String, Option<bool>>,
+    failure_logs: FailureLogs,
+    /// Values of `passthru.tests` that are not tests, along with their type
+    skipped: Vec<(String, String)>,
+}
+
+/// The tests of all the packages being checked, built together
+///
+/// Each run-tests check registers its package when created, and the
+/// first one to run builds the tests of all the registered packages in a
+/// single nix invocation. That way, they can be built in parallel and
+/// one failing test does not prevent the others from being built.
+#[derive(Default)]
+pub struct Batch {
+    pkgs: RefCell<BTreeSet<String>>,
+    /// The versions and packages whose tests were already built
+    done: RefCell<HashSet<(String, String)>>,
+    built: RefCell<HashMap<(String, String), Built>>,
+}
+
+impl Batch {
+    fn register(&self, pkg: &str) {
+        self.pkgs.borrow_mut().insert(pkg.to_string());
+    }
+
+    /// Returns the tests of the package, building along with them the
+    /// tests of all the registered packages that were not built yet
+    fn take(
+        &self,
+        killer: &Receiver<()>,
+        run_dir: &Path,
+        version: &str,
+        pkg: &str,
+    ) -> anyhow::Result<Built> {
+        self.register(pkg);
+        let key = (version.to_string(), pkg.to_string());
+        if !self.done.borrow().contains(&key) {
+            let pkgs = self
+                .pkgs
+                .borrow()
+                .iter()
+                .filter(|p| {
+                    !self
+                        .done
+                        .borrow()
+                        .contains(&(version.to_string(), p.to_string()))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let built = build(killer, run_dir, version, &pkgs)?;
+            let mut done = self.done.borrow_mut();
+            let mut all_built = self.built.borrow_mut();
+            for (pkg, built) in built {
+                done.insert((version.to_string(), pkg.clone()));
+                all_built.insert((version.to_string(), pkg), built);
+            }
+        }
+        Ok(self.built.borrow_mut().remove(&key).unwrap_or_default())
+    }
+}
+
+/// Builds the tests of the packages, walking nested sets of tests
+///
+/// The tests are built from their derivations, so that nixpkgs is only
+/// evaluated once for all of them.
 fn build(
     killer: &Receiver<()>,
+    run_dir: &Path,
     version: &str,
-    pkg: &str,
-) -> anyhow::Result<HashMap<String, Option<bool>>> {
-    let test_names = crate::nix(
+    pkgs: &[String],
+) -> anyhow::Result<HashMap<String, Built>> {
+    let entries = crate::nix(
         killer,
         &[
             "eval",
             "--json",
             &format!(
-                "(builtins.attrNames {}.{}.passthru.tests or {{}})",
+                "(let \
+                    walk = depth: path: v: \
+                      let kind = builtins.tryEval ( \
+                        if !(builtins.isAttrs v) then builtins.typeOf v \
+                        else if v.type or null == \"derivation\" then \"derivation\" \
+                        else if depth >= {} then \"too deeply nested set\" \
+                        else \"set\"); \
+                      in if !kind.success then [ {{ inherit path; }} ] \
+                      else if kind.value == \"derivation\" then \
+                        let drv = builtins.tryEval v.drvPath; \
+                        in [ (if drv.success then {{ inherit path; drv = drv.value; out = v.outPath; }} \
+                              else {{ inherit path; }}) ] \
+                      else if kind.value == \"set\" then \
+                        builtins.concatLists (map \
+                          (n: walk (depth + 1) (path ++ [ n ]) v.${{n}}) \
+                          (builtins.attrNames v)) \
+                      else [ {{ inherit path; skipped = kind.value; }} ]; \
+                    pkgs = {}; \
+                  in {{ {} }})",
+                MAX_DEPTH,
                 crate::nixpkgs(),
-                pkg
+                pkgs.iter()
+                    .map(|pkg| format!(
+                        "{:?} = walk 0 [] (pkgs.{}.passthru.tests or {{}});",
+                        pkg, pkg
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(" ")
             ),
         ],
     )
-    .with_context(|| format!("recovering the list of tests for {}", pkg))?
-    .as_ref()
-    .and_then(|names| names.as_array())
-    .and_then(|names| {
-        names
-            .iter()
-            .map(|t| t.as_str().map(|t| t.to_string()))
-            .collect::<Option<Vec<String>>>()
-    })
-    .unwrap_or_else(Vec::new);
+    .with_context(|| format!("recovering the list of {} tests of {:?}", version, pkgs))?;
+    let mut entries = match entries {
+        Some(entries) => serde_json::from_value::<HashMap<String, Vec<TestEntry>>>(entries)
+            .with_context(|| format!("parsing the list of {} tests of {:?}", version, pkgs))?,
+        None => HashMap::new(),
+    };
 
+    // Tests that fail evaluating are mapped to None
+    let mut tests = BTreeMap::new();
     let mut res = HashMap::new();
-    for test in test_names {
-        println!("running test {}", test);
-        let test_res = crate::run_nix(
-            killer,
-            false,
-            &[
-                "build",
-                &crate::nix_eval_for(&format!("{}.passthru.tests.{}", pkg, test)),
-            ],
-        )
-        .with_context(|| {
-            format!(
-                "building {} version of test {} in package {}",
-                version, test, pkg
+    for pkg in pkgs {
+        let mut pkg_tests = BTreeMap::new();
+        let mut skipped = Vec::new();
+        for entry in entries.remove(pkg).unwrap_or_default() {
+            let name = test_name(&entry.path);
+            match entry {
+                TestEntry {
+                    skipped: Some(kind),
+                    ..
+                } => skipped.push((name, kind)),
+                TestEntry {
+                    drv: Some(drv),
+                    out: Some(out),
+                    ..
+                } => {
+                    pkg_tests.insert(name, Some(TestPaths { drv, out }));
+                }
+                _ => {
+                    pkg_tests.insert(name, None);
+                }
+            }
+        }
+        tests.insert(pkg.clone(), pkg_tests);
+        res.insert(
+            pkg.clone(),
+            Built {
+                skipped,
+                ..Built::default()
+            },
+        );
+    }
+
+    let buildable = tests
+        .values()
+        .flat_map(|pkg_tests| pkg_tests.values())
+        .filter_map(|paths| paths.as_ref())
+        .map(|paths| format!("{}^*", paths.drv))
+        .collect::<Vec<String>>();
+    let log = if buildable.is_empty() {
+        Some(String::new())
+    } else {
+        for (pkg, pkg_tests) in &tests {
+            println!(
+                "running tests of {} {:?}",
+                pkg,
+                pkg_tests.keys().collect::<Vec<_>>()
+            );
+        }
+        let mut args = vec!["build", "--keep-going", "--no-link"];
+        args.extend(buildable.iter().map(|e| e as &str));
+        crate::run_nix_logged(killer, &args)
+            .with_context(|| format!("building {} version of tests of {:?}", version, pkgs))?
+            .map(|(_, log)| log)
+    };
+    let log = match log {
+        Some(log) => log,
+        None => {
+            for (pkg, pkg_tests) in tests {
+                let built = res.get_mut(&pkg).expect("package was not initialized");
+                for test in pkg_tests.keys() {
+                    built.results.insert(test.clone(), None);
+                }
+            }
+            return Ok(res);
+        }
+    };
+
+    let failed_drvs = crate::build_log::failed_drvs(&log);
+    for (pkg, pkg_tests) in tests {
+        let built = res.get_mut(&pkg).expect("package was not initialized");
+        for (test, paths) in pkg_tests {
+            let passed = match &paths {
+                Some(paths) => Path::new(&paths.out).exists(),
+                None => false,
+            };
+            // Tests that failed evaluating have no log
+            if let (false, Some(paths)) = (passed, &paths) {
+                if let Some(failure_log) = failure_log(killer, &paths.drv, &failed_drvs, run_dir)
+                    .with_context(|| {
+                        format!("recovering the log of test {} in package {}", test, pkg)
+                    })?
+                {
+                    built.failure_logs.insert(test.clone(), failure_log);
+                }
+            }
+            built.results.insert(test, Some(passed));
+        }
+    }
+
+    Ok(res)
+}
+
+/// Returns the log of the failed derivation among the test and its
+/// dependencies, `Some(None)` if none of them is known to have failed, or
+/// `None` if interrupted
+///
+/// The stderr of nix mixes the builds of all the tests, so it is not used
+/// as a fallback like for the build of a single package.
+fn failure_log(
+    killer: &Receiver<()>,
+    drv: &str,
+    failed_drvs: &[String],
+    run_dir: &Path,
+) -> anyhow::Result<Option<Option<crate::build_log::FailureLog>>> {
+    let failed = if failed_drvs.iter().any(|d| d == drv) {
+        Some(drv.to_string())
+    } else {
+        let deps = match crate::run_nix(killer, true, &["path-info", "--recursive", drv])
+            .with_context(|| format!("listing the dependencies of {}", drv))?
+        {
+            Some(out) => String::from_utf8_lossy(&out.stdout).into_owned(),
+            None => return Ok(None),
+        };
+        let deps = deps.lines().collect::<HashSet<_>>();
+        failed_drvs
+            .iter()
+            .find(|d| deps.contains(d.as_str()))
+            .cloned()
+    };
+    match failed {
+        Some(failed) => {
+            Ok(
+                crate::build_log::FailureLog::recover_drv(killer, Some(failed), "", run_dir)?
+                    .map(Some),
             )
-        })?
-        .map(|out| out.status.success());
-        res.insert(test, test_res);
+        }
+        None => Ok(Some(None)),
     }
+}
+
+struct TestPaths {
+    drv: String,
+    out: String,
+}
+
+/// A value found while walking `passthru.tests`: a derivation, a value
+/// that is not a test, or a derivation or set that failed evaluating
+#[derive(serde::Deserialize)]
+struct TestEntry {
+    path: Vec<String>,
+    drv: Option<String>,
+    out: Option<String>,
+    skipped: Option<String>,
+}
+
+/// Returns the dotted attribute path of a test, below `passthru.tests`
+fn test_name(path: &[String]) -> String {
+    path.iter()
+        .map(|a| {
+            let is_identifier = a
+                .chars()
+                .next()
+                .map(|c| c.is_ascii_alphabetic() || c == '_')
+                .unwrap_or(false)
+                && a.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_'-".contains(c));
+            if is_identifier {
+                a.clone()
+            } else {
+                format!("{:?}", a)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
 
+/// Reruns each of the tests `times` times, and returns how many of all
+/// their runs passed, counting the initial run whose result is given
+/// along with the test name
+fn rerun(
+    killer: &Receiver<()>,
+    times: u32,
+    version: &str,
+    pkg: &str,
+    tests: Vec<(String, bool)>,
+) -> anyhow::Result<HashMap<String, Runs>> {
+    let mut res = HashMap::new();
+    if times == 0 {
+        return Ok(res);
+    }
+    'tests: for (test, passed) in tests {
+        let expr = crate::nix_eval_for(&format!("{}.passthru.tests.{}", pkg, test));
+        let mut last_passed = passed;
+        let mut runs = Runs {
+            passed: passed as u32,
+            total: 1,
+        };
+        for i in 0..times {
+            println!("rerunning test {} ({}/{})", test, i + 1, times);
+            // A test that passed is in the store, so it has to be rebuilt
+            // to be run again
+            let mut args = vec!["build", "--no-link"];
+            if last_passed {
+                args.push("--rebuild");
+            }
+            args.push(&expr);
+            let (status, log) = match crate::run_nix_logged(killer, &args).with_context(|| {
+                format!(
+                    "rerunning {} version of test {} in package {}",
+                    version, test, pkg
+                )
+            })? {
+                Some(r) => r,
+                None => break 'tests,
+            };
+            // A rebuild with different outputs still means the test passed
+            last_passed = status.success() || log.contains("may not be deterministic");
+            runs.total += 1;
+            if last_passed {
+                runs.passed += 1;
+            }
+        }
+        res.insert(test, runs);
+    }
     Ok(res)
 }
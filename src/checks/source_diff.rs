@@ -0,0 +1,270 @@
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Maximum number of files listed per category in the report
+const MAX_LISTED: usize = 50;
+
+/// Names of the files that drive the build
+const BUILD_SCRIPT_NAMES: &[&str] = &[
+    "configure",
+    "configure.ac",
+    "configure.in",
+    "Makefile.am",
+    "Makefile.in",
+    "CMakeLists.txt",
+    "meson.build",
+    "meson_options.txt",
+    "meson.options",
+    "build.rs",
+    "setup.py",
+    "build.zig",
+    "SConstruct",
+];
+
+/// Extensions of the files that drive the build
+const BUILD_SCRIPT_EXTENSIONS: &[&str] = &["m4", "cmake"];
+
+/// Names of the directories holding tests and their fixtures
+const TEST_DIR_NAMES: &[&str] = &[
+    "test",
+    "tests",
+    "testdata",
+    "test-data",
+    "fixtures",
+    "__tests__",
+];
+
+/// How the upstream source changed
+struct Diff {
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    changed: Vec<PathBuf>,
+    /// Files that are binary among the added and changed ones
+    binary: Vec<PathBuf>,
+}
+
+enum Outcome {
+    NoSource,
+    Unchanged,
+    Changed(Diff),
+}
+
+pub struct Chk {
+    pkg: String,
+    sources: Rc<crate::sources::Shared>,
+    before: Option<Rc<crate::sources::Source>>,
+    result: Option<Outcome>,
+}
+
+impl Chk {
+    pub fn new(pkg: String, sources: Rc<crate::sources::Shared>) -> Chk {
+        Chk {
+            pkg,
+            sources,
+            before: None,
+            result: None,
+        }
+    }
+}
+
+impl crate::Check for Chk {
+    fn uuid(&self) -> crate::CheckId {
+        crate::CheckId::from_uuid_param(
+            uuid::Uuid::from_u128(0x78778da92fe146729e02c83c254507d8),
+            &self.pkg,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("source-diff({})", self.pkg)
+    }
+
+    fn run_before(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        self.before = self.sources.get(killer, "base")?;
+        Ok(())
+    }
+
+    fn run_after(&mut self, killer: &Receiver<()>) -> anyhow::Result<()> {
+        let after = match self.sources.get(killer, "to-check")? {
+            Some(after) => after,
+            None => return Ok(()),
+        };
+        let before = match &self.before {
+            Some(before) => before,
+            None => return Ok(()),
+        };
+        self.result = Some(match (&before.tree, &after.tree) {
+            (Some(before), Some(after)) if before == after => Outcome::Unchanged,
+            (Some(before), Some(after)) => {
+                let diff = diff(before, after)?;
+                if diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty() {
+                    Outcome::Unchanged
+                } else {
+                    Outcome::Changed(diff)
+                }
+            }
+            _ => Outcome::NoSource,
+        });
+        Ok(())
+    }
+
+    fn additional_needed_tests(&self) -> anyhow::Result<Vec<Box<dyn crate::Check>>> {
+        Ok(Vec::new())
+    }
+
+    fn report(&self) -> String {
+        let diff = match &self.result {
+            None => {
+                return format!(
+                    "**source changes of {}:** 😢 check was interrupted",
+                    self.pkg
+                )
+            }
+            Some(Outcome::NoSource) => {
+                return format!(
+                "**source changes of {}:** 😢 the base or to-check source could not be unpacked",
+                self.pkg
+            )
+            }
+            Some(Outcome::Unchanged) => {
+                return format!(
+                    "**source changes of {}:** ✔ the source did not change",
+                    self.pkg
+                )
+            }
+            Some(Outcome::Changed(diff)) => diff,
+        };
+
+        let mut res = format!(
+            "**source changes of {}:** {} files added, {} removed, {} changed\n",
+            self.pkg,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len()
+        );
+        let touched = diff.added.iter().chain(diff.changed.iter());
+        let build_scripts = touched
+            .clone()
+            .filter(|f| is_build_script(f))
+            .collect::<Vec<_>>();
+        let fixtures = touched.filter(|f| is_test_file(f)).collect::<Vec<_>>();
+        let binary_fixtures = fixtures
+            .iter()
+            .filter(|f| diff.binary.contains(f))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Binary test fixtures are where the xz backdoor was hidden
+        res += &category(
+            "😢 **binary test fixtures added or changed**",
+            &binary_fixtures,
+        );
+        res += &category(
+            "😢 **binary files added or changed**",
+            &diff
+                .binary
+                .iter()
+                .filter(|f| !is_test_file(f))
+                .collect::<Vec<_>>(),
+        );
+        res += &category("😢 **build scripts added or changed**", &build_scripts);
+        res += &category(
+            "test files added or changed",
+            &fixtures
+                .iter()
+                .filter(|f| !diff.binary.contains(f))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+
+        res += "\n<details><summary>all changed files</summary>\n\n";
+        for (title, files) in &[
+            ("added", &diff.added),
+            ("removed", &diff.removed),
+            ("changed", &diff.changed),
+        ] {
+            res += &category(title, &files.iter().collect::<Vec<_>>());
+        }
+        res += "\n</details>\n";
+        res
+    }
+}
+
+/// Returns a list item with the files, or nothing if there are none
+fn category(title: &str, files: &[&PathBuf]) -> String {
+    if files.is_empty() {
+        return String::new();
+    }
+    let mut res = format!("  * {}:\n", title);
+    for f in files.iter().take(MAX_LISTED) {
+        res += &format!("    * `{}`\n", f.display());
+    }
+    if files.len() > MAX_LISTED {
+        res += &format!("    * … and {} more\n", files.len() - MAX_LISTED);
+    }
+    res
+}
+
+fn diff(before: &Path, after: &Path) -> anyhow::Result<Diff> {
+    let files_before = crate::sources::files(before)?;
+    let files_after = crate::sources::files(after)?;
+    let mut res = Diff {
+        added: files_after.difference(&files_before).cloned().collect(),
+        removed: files_before.difference(&files_after).cloned().collect(),
+        changed: Vec::new(),
+        binary: Vec::new(),
+    };
+    for f in files_before.intersection(&files_after) {
+        if !crate::same_file(&before.join(f), &after.join(f))? {
+            res.changed.push(f.clone());
+        }
+    }
+    for f in res.added.iter().chain(res.changed.iter()) {
+        if is_binary(&after.join(f))? {
+            res.binary.push(f.clone());
+        }
+    }
+    res.binary.sort();
+    Ok(res)
+}
+
+fn is_build_script(file: &Path) -> bool {
+    let name_matches = file
+        .file_name()
+        .map(|n| BUILD_SCRIPT_NAMES.iter().any(|b| n == *b))
+        .unwrap_or(false);
+    let extension_matches = file
+        .extension()
+        .map(|e| BUILD_SCRIPT_EXTENSIONS.iter().any(|b| e == *b))
+        .unwrap_or(false);
+    name_matches || extension_matches
+}
+
+fn is_test_file(file: &Path) -> bool {
+    file.parent()
+        .map(|dir| {
+            dir.components()
+                .any(|c| TEST_DIR_NAMES.iter().any(|t| c.as_os_str() == *t))
+        })
+        .unwrap_or(false)
+}
+
+/// Returns true iff the file is a regular file whose beginning contains
+/// a NUL byte, like git decides
+fn is_binary(file: &Path) -> anyhow::Result<bool> {
+    let meta = std::fs::symlink_metadata(file)
+        .with_context(|| format!("reading the metadata of {:?}", file))?;
+    if !meta.is_file() {
+        return Ok(false);
+    }
+    let mut start = Vec::new();
+    std::fs::File::open(file)
+        .and_then(|f| f.take(8000).read_to_end(&mut start))
+        .with_context(|| format!("reading {:?}", file))?;
+    Ok(start.contains(&0))
+}
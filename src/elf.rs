@@ -0,0 +1,581 @@
+//! Minimal ELF parsing, only reading the bits the checks are interested in
+
+use anyhow::{anyhow, Context};
+use std::{io::Read, path::Path};
+
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_GNU_STACK: u32 = 0x6474e551;
+const PT_GNU_RELRO: u32 = 0x6474e552;
+
+const PF_X: u32 = 1;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_RPATH: u64 = 15;
+const DT_BIND_NOW: u64 = 24;
+const DT_RUNPATH: u64 = 29;
+const DT_FLAGS: u64 = 30;
+const DT_FLAGS_1: u64 = 0x6ffffffb;
+
+const DF_BIND_NOW: u64 = 0x8;
+const DF_1_NOW: u64 = 0x1;
+const DF_1_PIE: u64 = 0x08000000;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+
+struct ProgramHeader {
+    p_type: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+pub struct Elf {
+    pub elf_type: u16,
+    program_headers: Vec<ProgramHeader>,
+    pub interpreter: Option<String>,
+    pub needed: Vec<String>,
+    pub rpath: Option<String>,
+    pub runpath: Option<String>,
+    pub bind_now: bool,
+    pie_flag: bool,
+    pub sections: Vec<String>,
+    pub symbols: Vec<String>,
+}
+
+/// Returns `None` if the file is not an ELF file, or is a malformed one
+/// (which happens eg. with test fixtures)
+pub fn read(path: &Path) -> anyhow::Result<Option<Elf>> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening {:?}", path))?;
+    let mut magic = [0; 4];
+    match file.read_exact(&mut magic) {
+        Ok(()) if magic == *b"\x7fELF" => (),
+        Ok(()) => return Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading {:?}", path)),
+    }
+    let mut data = magic.to_vec();
+    file.read_to_end(&mut data)
+        .with_context(|| format!("reading {:?}", path))?;
+    Ok(parse(&data).ok())
+}
+
+fn parse(data: &[u8]) -> anyhow::Result<Elf> {
+    let r = Reader {
+        data,
+        is_64: match data.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err(anyhow!("unknown ELF class")),
+        },
+        big_endian: match data.get(5) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err(anyhow!("unknown ELF data encoding")),
+        },
+    };
+
+    // Read the program headers
+    let elf_type = r.u16(16)?;
+    let (phoff, phentsize, phnum) = if r.is_64 {
+        (r.u64(32)?, r.u16(54)?, r.u16(56)?)
+    } else {
+        (r.u32(28)? as u64, r.u16(42)?, r.u16(44)?)
+    };
+    let program_headers = (0..phnum as u64)
+        .map(|i| {
+            let o = r.entry(phoff, i, phentsize as u64)?;
+            Ok(if r.is_64 {
+                ProgramHeader {
+                    p_type: r.u32(o)?,
+                    flags: r.u32(o + 4)?,
+                    offset: r.u64(o + 8)?,
+                    vaddr: r.u64(o + 16)?,
+                    filesz: r.u64(o + 32)?,
+                }
+            } else {
+                ProgramHeader {
+                    p_type: r.u32(o)?,
+                    offset: r.u32(o + 4)? as u64,
+                    vaddr: r.u32(o + 8)? as u64,
+                    filesz: r.u32(o + 16)? as u64,
+                    flags: r.u32(o + 24)?,
+                }
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("reading the program headers")?;
+
+    let mut res = Elf {
+        elf_type,
+        program_headers,
+        interpreter: None,
+        needed: Vec::new(),
+        rpath: None,
+        runpath: None,
+        bind_now: false,
+        pie_flag: false,
+        sections: Vec::new(),
+        symbols: Vec::new(),
+    };
+
+    // Section headers are not needed to run the file, and are often
+    // stripped or mangled: only read them if they are well-formed
+    if let Ok((sections, symbols)) = read_sections(&r) {
+        res.sections = sections;
+        res.symbols = symbols;
+    }
+
+    for ph in &res.program_headers {
+        if ph.p_type == PT_INTERP {
+            let interp = r.bytes(ph.offset, ph.filesz)?;
+            let interp = interp.split(|&b| b == 0).next().unwrap_or(interp);
+            res.interpreter = Some(String::from_utf8_lossy(interp).into_owned());
+        }
+    }
+
+    // Read the dynamic section
+    let dynamic = match res
+        .program_headers
+        .iter()
+        .find(|ph| ph.p_type == PT_DYNAMIC)
+    {
+        Some(ph) => ph,
+        None => return Ok(res),
+    };
+    let entsize = if r.is_64 { 16 } else { 8 };
+    let mut entries = Vec::new();
+    for i in 0..(dynamic.filesz / entsize) {
+        let o = r.entry(dynamic.offset, i, entsize)?;
+        let (tag, val) = if r.is_64 {
+            (r.u64(o)?, r.u64(o + 8)?)
+        } else {
+            (r.u32(o)? as u64, r.u32(o + 4)? as u64)
+        };
+        if tag == DT_NULL {
+            break;
+        }
+        entries.push((tag, val));
+    }
+    let strtab = match entries.iter().find(|(tag, _)| *tag == DT_STRTAB) {
+        Some((_, addr)) => res
+            .vaddr_to_offset(*addr)
+            .ok_or_else(|| anyhow!("string table is not in a loaded segment"))?,
+        None => return Ok(res),
+    };
+    let string = |val: u64| r.str(r.offset(strtab, val)?);
+    for (tag, val) in entries {
+        match tag {
+            DT_NEEDED => res.needed.push(string(val)?),
+            DT_RPATH => res.rpath = Some(string(val)?),
+            DT_RUNPATH => res.runpath = Some(string(val)?),
+            DT_BIND_NOW => res.bind_now = true,
+            DT_FLAGS if val & DF_BIND_NOW != 0 => res.bind_now = true,
+            DT_FLAGS_1 => {
+                res.bind_now |= val & DF_1_NOW != 0;
+                res.pie_flag = val & DF_1_PIE != 0;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Returns the section names and the symbols of the ELF file
+fn read_sections(r: &Reader) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let (shoff, shentsize, shnum, shstrndx) = if r.is_64 {
+        (r.u64(40)?, r.u16(58)?, r.u16(60)?, r.u16(62)?)
+    } else {
+        (r.u32(32)? as u64, r.u16(46)?, r.u16(48)?, r.u16(50)?)
+    };
+    let mut sections = Vec::new();
+    let mut symbols = Vec::new();
+    if shoff == 0 {
+        return Ok((sections, symbols));
+    }
+
+    // (name offset, type, offset, size, link, entry size)
+    let headers = (0..shnum as u64)
+        .map(|i| {
+            let o = r.entry(shoff, i, shentsize as u64)?;
+            Ok(if r.is_64 {
+                (
+                    r.u32(o)? as u64,
+                    r.u32(o + 4)?,
+                    r.u64(o + 24)?,
+                    r.u64(o + 32)?,
+                    r.u32(o + 40)?,
+                    r.u64(o + 56)?,
+                )
+            } else {
+                (
+                    r.u32(o)? as u64,
+                    r.u32(o + 4)?,
+                    r.u32(o + 16)? as u64,
+                    r.u32(o + 20)? as u64,
+                    r.u32(o + 24)?,
+                    r.u32(o + 36)? as u64,
+                )
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if let Some(&(_, _, names, _, _, _)) = headers.get(shstrndx as usize) {
+        for &(name, ..) in &headers {
+            sections.push(r.str(r.offset(names, name)?)?);
+        }
+    }
+
+    for &(_, sh_type, offset, size, link, entsize) in &headers {
+        if (sh_type != SHT_SYMTAB && sh_type != SHT_DYNSYM) || entsize == 0 {
+            continue;
+        }
+        let strtab = match headers.get(link as usize) {
+            Some(&(_, _, strtab, _, _, _)) => strtab,
+            None => continue,
+        };
+        for i in 1..(size / entsize) {
+            let name = r.u32(r.entry(offset, i, entsize)?)? as u64;
+            if name != 0 {
+                symbols.push(r.str(r.offset(strtab, name)?)?);
+            }
+        }
+    }
+
+    Ok((sections, symbols))
+}
+
+impl Elf {
+    /// Whether this is a position-independent executable
+    pub fn is_pie(&self) -> bool {
+        self.elf_type == ET_DYN && (self.pie_flag || self.interpreter.is_some())
+    }
+
+    /// Whether some of the data is made read-only after relocation
+    pub fn has_relro(&self) -> bool {
+        self.program_headers
+            .iter()
+            .any(|ph| ph.p_type == PT_GNU_RELRO)
+    }
+
+    /// Whether the stack is non-executable
+    pub fn has_nx(&self) -> bool {
+        self.program_headers
+            .iter()
+            .any(|ph| ph.p_type == PT_GNU_STACK && ph.flags & PF_X == 0)
+    }
+
+    fn vaddr_to_offset(&self, addr: u64) -> Option<u64> {
+        self.program_headers
+            .iter()
+            .find(|ph| {
+                ph.p_type == PT_LOAD
+                    && ph.vaddr <= addr
+                    && ph
+                        .vaddr
+                        .checked_add(ph.filesz)
+                        .map(|end| addr < end)
+                        .unwrap_or(false)
+            })
+            .and_then(|ph| (addr - ph.vaddr).checked_add(ph.offset))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    /// Returns the offset `rel` bytes into a table
+    fn offset(&self, table: u64, rel: u64) -> anyhow::Result<u64> {
+        table
+            .checked_add(rel)
+            .ok_or_else(|| anyhow!("offset {} in the table at offset {} overflows", rel, table))
+    }
+
+    /// Returns the offset of the `i`-th entry of a table, checking that it
+    /// starts within the file
+    fn entry(&self, table: u64, i: u64, entsize: u64) -> anyhow::Result<u64> {
+        i.checked_mul(entsize)
+            .and_then(|o| o.checked_add(table))
+            .filter(|&o| o < self.data.len() as u64)
+            .ok_or_else(|| {
+                anyhow!(
+                    "entry {} of the table at offset {} is past the end of the file",
+                    i,
+                    table
+                )
+            })
+    }
+
+    fn bytes(&self, offset: u64, len: u64) -> anyhow::Result<&'a [u8]> {
+        let start = offset as usize;
+        let end = start.checked_add(len as usize);
+        end.and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| anyhow!("reading past the end of the file at offset {}", offset))
+    }
+
+    fn uint(&self, offset: u64, len: u64) -> anyhow::Result<u64> {
+        let bytes = self.bytes(offset, len)?;
+        let add_byte = |acc: u64, b: &u8| (acc << 8) | *b as u64;
+        Ok(if self.big_endian {
+            bytes.iter().fold(0, add_byte)
+        } else {
+            bytes.iter().rev().fold(0, add_byte)
+        })
+    }
+
+    fn u16(&self, offset: u64) -> anyhow::Result<u16> {
+        Ok(self.uint(offset, 2)? as u16)
+    }
+
+    fn u32(&self, offset: u64) -> anyhow::Result<u32> {
+        Ok(self.uint(offset, 4)? as u32)
+    }
+
+    fn u64(&self, offset: u64) -> anyhow::Result<u64> {
+        self.uint(offset, 8)
+    }
+
+    fn str(&self, offset: u64) -> anyhow::Result<String> {
+        let rest = self
+            .data
+            .get(offset as usize..)
+            .ok_or_else(|| anyhow!("reading past the end of the file at offset {}", offset))?;
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow!("unterminated string at offset {}", offset))?;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the little-endian `value` on `len` bytes at `offset`
+    fn put(data: &mut Vec<u8>, offset: usize, len: usize, value: u64) {
+        if data.len() < offset + len {
+            data.resize(offset + len, 0);
+        }
+        data[offset..offset + len].copy_from_slice(&value.to_le_bytes()[..len]);
+    }
+
+    /// Returns the ELF header of a little-endian file, with no program
+    /// or section header
+    fn header(is_64: bool, elf_type: u16) -> Vec<u8> {
+        let mut data = b"\x7fELF".to_vec();
+        data.push(if is_64 { 2 } else { 1 });
+        data.push(1);
+        put(&mut data, 16, 2, elf_type as u64);
+        let (len, phentsize, shentsize) = if is_64 { (64, 56, 64) } else { (52, 32, 40) };
+        put(&mut data, if is_64 { 54 } else { 42 }, 2, phentsize);
+        put(&mut data, if is_64 { 58 } else { 46 }, 2, shentsize);
+        data.resize(len, 0);
+        data
+    }
+
+    fn set_program_headers(data: &mut Vec<u8>, phoff: u64, phnum: u16) {
+        if data[4] == 2 {
+            put(data, 32, 8, phoff);
+            put(data, 56, 2, phnum as u64);
+        } else {
+            put(data, 28, 4, phoff);
+            put(data, 44, 2, phnum as u64);
+        }
+    }
+
+    fn set_section_headers(data: &mut Vec<u8>, shoff: u64, shnum: u16) {
+        if data[4] == 2 {
+            put(data, 40, 8, shoff);
+            put(data, 60, 2, shnum as u64);
+        } else {
+            put(data, 32, 4, shoff);
+            put(data, 48, 2, shnum as u64);
+        }
+    }
+
+    /// Writes a program header at `at`, as (type, flags, offset, vaddr,
+    /// filesz)
+    fn put_program_header(data: &mut Vec<u8>, at: usize, ph: (u32, u32, u64, u64, u64)) {
+        let (p_type, flags, offset, vaddr, filesz) = ph;
+        if data[4] == 2 {
+            put(data, at, 4, p_type as u64);
+            put(data, at + 4, 4, flags as u64);
+            put(data, at + 8, 8, offset);
+            put(data, at + 16, 8, vaddr);
+            put(data, at + 32, 8, filesz);
+            put(data, at + 40, 8, filesz);
+        } else {
+            put(data, at, 4, p_type as u64);
+            put(data, at + 4, 4, offset);
+            put(data, at + 8, 4, vaddr);
+            put(data, at + 16, 4, filesz);
+            put(data, at + 20, 4, filesz);
+            put(data, at + 24, 4, flags as u64);
+        }
+    }
+
+    /// Builds a dynamically linked PIE with an interpreter, a needed
+    /// library and a runpath
+    fn dynamic_executable(is_64: bool) -> Vec<u8> {
+        let mut data = header(is_64, ET_DYN);
+        let phoff = data.len();
+        let phentsize = if is_64 { 56 } else { 32 };
+        let (entsize, word) = if is_64 { (16, 8) } else { (8, 4) };
+        let interp = phoff + 4 * phentsize;
+        let strtab = interp + 16;
+        let strings = b"\0libc.so.6\0/run/path\0";
+        let dynamic = strtab + strings.len();
+        let entries = [
+            (DT_NEEDED, 1),
+            (DT_RUNPATH, 11),
+            (DT_STRTAB, 0x1000 + strtab as u64),
+            (DT_FLAGS_1, DF_1_NOW | DF_1_PIE),
+            (DT_NULL, 0),
+        ];
+        let len = dynamic + entries.len() * entsize;
+
+        set_program_headers(&mut data, phoff as u64, 4);
+        let headers = [
+            (PT_LOAD, 5, 0, 0x1000, len as u64),
+            (PT_INTERP, 4, interp as u64, 0, 16),
+            (
+                PT_DYNAMIC,
+                6,
+                dynamic as u64,
+                0,
+                (entries.len() * entsize) as u64,
+            ),
+            (PT_GNU_STACK, 6, 0, 0, 0),
+        ];
+        for (i, ph) in headers.iter().enumerate() {
+            put_program_header(&mut data, phoff + i * phentsize, *ph);
+        }
+        data.resize(interp, 0);
+        data.extend_from_slice(b"/lib/ld.so\0\0\0\0\0\0");
+        data.extend_from_slice(strings);
+        for (i, (tag, val)) in entries.iter().enumerate() {
+            put(&mut data, dynamic + i * entsize, word, *tag);
+            put(&mut data, dynamic + i * entsize + word, word, *val);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_dynamic_executables() {
+        for is_64 in [false, true] {
+            let elf = parse(&dynamic_executable(is_64)).expect("failed parsing valid ELF");
+            assert_eq!(elf.elf_type, ET_DYN);
+            assert_eq!(elf.interpreter.as_deref(), Some("/lib/ld.so"));
+            assert_eq!(elf.needed, vec!["libc.so.6".to_string()]);
+            assert_eq!(elf.runpath.as_deref(), Some("/run/path"));
+            assert_eq!(elf.rpath, None);
+            assert!(elf.bind_now);
+            assert!(elf.is_pie());
+            assert!(elf.has_nx());
+            assert!(!elf.has_relro());
+            assert!(elf.sections.is_empty());
+        }
+    }
+
+    #[test]
+    fn parses_minimal_files() {
+        for is_64 in [false, true] {
+            let elf = parse(&header(is_64, ET_EXEC)).expect("failed parsing minimal ELF");
+            assert_eq!(elf.elf_type, ET_EXEC);
+            assert_eq!(elf.interpreter, None);
+            assert!(elf.needed.is_empty());
+            assert!(!elf.is_pie());
+            assert!(!elf.has_nx());
+        }
+    }
+
+    #[test]
+    fn reads_only_elf_files() {
+        let dir = tempfile::tempdir().expect("failed creating temporary directory");
+        let cases: &[(&str, &[u8], bool)] = &[
+            ("empty", b"", false),
+            ("script", b"#!/bin/sh\n", false),
+            ("truncated", b"\x7fELF\x02\x01", false),
+            ("minimal", &header(true, ET_EXEC), true),
+        ];
+        for (name, contents, is_elf) in cases {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).expect("failed writing test file");
+            let elf = read(&path).expect("failed reading test file");
+            assert_eq!(elf.is_some(), *is_elf, "for {} file", name);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        for is_64 in [false, true] {
+            let data = dynamic_executable(is_64);
+            for len in [5, 17, 30, 50, data.len() - 1] {
+                assert!(parse(&data[..len]).is_err(), "for length {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_program_headers_past_the_end() {
+        for is_64 in [false, true] {
+            let mut data = header(is_64, ET_EXEC);
+            let len = data.len() as u64;
+            set_program_headers(&mut data, len, u16::MAX);
+            assert!(parse(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_wrapping_program_header_offsets() {
+        let mut data = header(true, ET_EXEC);
+        set_program_headers(&mut data, u64::MAX - 10, 2);
+        assert!(parse(&data).is_err());
+    }
+
+    #[test]
+    fn ignores_malformed_section_headers() {
+        let cases = [(u64::MAX - 8, 2), (64, u16::MAX), (1 << 40, 1)];
+        for (shoff, shnum) in cases {
+            let mut data = header(true, ET_EXEC);
+            set_section_headers(&mut data, shoff, shnum);
+            let elf = parse(&data).expect("failed parsing ELF with malformed sections");
+            assert!(elf.sections.is_empty());
+            assert!(elf.symbols.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_huge_segments() {
+        for p_type in [PT_INTERP, PT_DYNAMIC] {
+            let mut data = header(true, ET_DYN);
+            set_program_headers(&mut data, 64, 1);
+            put_program_header(&mut data, 64, (p_type, 4, 120, 0, u64::MAX));
+            // Without any DT_NULL entry to stop at
+            data.resize(data.len() + 64, 0xff);
+            assert!(parse(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_string_tables_out_of_loaded_segments() {
+        let mut data = dynamic_executable(true);
+        // Make the loaded segment wrap around the address space
+        put_program_header(&mut data, 64, (PT_LOAD, 5, 0, 0x1000, u64::MAX));
+        assert!(parse(&data).is_err());
+    }
+}
@@ -1,8 +1,15 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use crossbeam_channel::Receiver;
-use std::path::Path;
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
 
+mod build_log;
 pub mod checks;
+mod elf;
+mod meta;
+mod sources;
 
 #[derive(PartialEq, Eq)]
 pub struct CheckId(String);
@@ -49,15 +56,52 @@ pub trait Check {
     fn report(&self) -> String;
 }
 
+/// Options, set on the command line, that alter how the checks are run
+pub struct Options {
+    /// Whether binaries should be run in a sandbox, without network
+    /// access and with a throwaway home directory
+    pub sandbox_binaries: bool,
+
+    /// Whether the packages should be rebuilt to check they are
+    /// reproducible
+    pub check_reproducibility: bool,
+
+    /// Build time ratio above which a build is reported as having become
+    /// slower or faster
+    pub build_time_threshold: f64,
+
+    /// Number of times the failing tests, and those whose result changed,
+    /// are rerun to detect flaky ones
+    pub rerun_tests: u32,
+
+    /// Directory in which to save the files referenced by the report,
+    /// like build logs
+    pub run_dir: std::path::PathBuf,
+}
+
+/// A commit of the changes being checked
+pub struct Commit {
+    pub id: String,
+    pub summary: String,
+    pub message: String,
+    pub is_merge: bool,
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct State {
     last_contributing: Option<String>,
+
+    /// The GitHub handle of the user, not to be mentioned in the report,
+    /// or the empty string if they chose not to give it
+    #[serde(default)]
+    github_handle: Option<String>,
 }
 
 impl Default for State {
     fn default() -> State {
         State {
             last_contributing: None,
+            github_handle: None,
         }
     }
 }
@@ -80,9 +124,30 @@ fn nix_eval_for(pkg: &str) -> String {
     format!("({}.{})", nixpkgs(), pkg)
 }
 
+/// Returns whether the package exists, not counting aliases, or `None`
+/// if interrupted
+fn attr_exists(killer: &Receiver<()>, pkg: &str) -> anyhow::Result<Option<bool>> {
+    Ok(nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "((import ./. {{ overlays = []; config.allowAliases = false; }}) ? {})",
+                pkg
+            ),
+        ],
+    )
+    .with_context(|| format!("checking whether package {} exists", pkg))?
+    .map(|exists| exists.as_bool().unwrap_or(false)))
+}
+
 fn nix(killer: &Receiver<()>, args: &[&str]) -> anyhow::Result<Option<serde_json::Value>> {
     run_nix(killer, true, args)?
         .map(|out| {
+            if !out.status.success() {
+                bail!("nix exited with {}", out.status);
+            }
             serde_json::from_slice(&out.stdout).context("parsing the output of the nix command")
         })
         .transpose()
@@ -103,14 +168,76 @@ fn run(
     args: &[&str],
 ) -> anyhow::Result<Option<std::process::Output>> {
     let mut process = std::process::Command::new(path);
-    process.args(args).stderr(std::process::Stdio::inherit());
+    process.args(args);
+    run_command(killer, capture_stdout, process)
+}
+
+fn run_command(
+    killer: &Receiver<()>,
+    capture_stdout: bool,
+    mut process: std::process::Command,
+) -> anyhow::Result<Option<std::process::Output>> {
+    process.stderr(std::process::Stdio::inherit());
     if capture_stdout {
         process.stdout(std::process::Stdio::piped());
     } else {
         process.stdout(std::process::Stdio::inherit());
     }
     let mut child = process.spawn().context("spawning the nix command")?;
-    while child.try_wait().context("waiting for nix")?.is_none() {
+    if wait_killable(killer, &mut child)?.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(child.wait_with_output().context(
+        "retrieving the output from a known-completed process",
+    )?))
+}
+
+/// Runs nix without capturing its stdout, and returns what it printed
+/// on stderr, while still forwarding it to the user
+fn run_nix_logged(
+    killer: &Receiver<()>,
+    args: &[&str],
+) -> anyhow::Result<Option<(std::process::ExitStatus, String)>> {
+    let mut child = std::process::Command::new("nix")
+        .args(args)
+        .stdout(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("spawning the nix command")?;
+    let mut stderr = child.stderr.take().expect("piped stderr is not available");
+    let tee = std::thread::spawn(move || -> std::io::Result<Vec<u8>> {
+        let mut log = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let len = stderr.read(&mut buf)?;
+            if len == 0 {
+                return Ok(log);
+            }
+            std::io::stderr().write_all(&buf[..len])?;
+            log.extend_from_slice(&buf[..len]);
+        }
+    });
+    let status = match wait_killable(killer, &mut child)? {
+        Some(status) => status,
+        None => return Ok(None),
+    };
+    let log = tee
+        .join()
+        .expect("the thread forwarding stderr panicked")
+        .context("forwarding the stderr of nix")?;
+    Ok(Some((status, String::from_utf8_lossy(&log).into_owned())))
+}
+
+/// Waits for the child to complete, killing it and returning `None` if
+/// the user asks for interruption
+fn wait_killable(
+    killer: &Receiver<()>,
+    child: &mut std::process::Child,
+) -> anyhow::Result<Option<std::process::ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait().context("waiting for nix")? {
+            return Ok(Some(status));
+        }
         if let Ok(()) = killer.recv_timeout(std::time::Duration::from_millis(50)) {
             // Leave some time for the ctrl-c to reach nix
             std::thread::sleep(std::time::Duration::from_millis(200));
@@ -118,9 +245,57 @@ fn run(
             return Ok(None);
         }
     }
-    Ok(Some(child.wait_with_output().context(
-        "retrieving the output from a known-completed process",
-    )?))
+}
+
+/// Lists everything below `path`, without following symlinks
+fn walk_dir(path: &Path) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    let mut res = Vec::new();
+    let mut to_visit = vec![path.to_path_buf()];
+    while let Some(dir) = to_visit.pop() {
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("listing directory {:?}", dir))?;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("listing directory {:?}", dir))?;
+            let file_type = entry
+                .file_type()
+                .with_context(|| format!("reading the file type of {:?}", entry.path()))?;
+            if file_type.is_dir() {
+                to_visit.push(entry.path());
+            }
+            res.push(entry.path());
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+/// Returns true iff both paths have the same type and contents, without
+/// following symlinks
+fn same_file(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    let meta_a =
+        std::fs::symlink_metadata(a).with_context(|| format!("reading the metadata of {:?}", a))?;
+    let meta_b =
+        std::fs::symlink_metadata(b).with_context(|| format!("reading the metadata of {:?}", b))?;
+    if meta_a.file_type() != meta_b.file_type() {
+        return Ok(false);
+    }
+    if meta_a.file_type().is_symlink() {
+        return Ok(std::fs::read_link(a)? == std::fs::read_link(b)?);
+    }
+    if meta_a.is_file() {
+        return Ok(meta_a.len() == meta_b.len()
+            && std::fs::read(a).with_context(|| format!("reading {:?}", a))?
+                == std::fs::read(b).with_context(|| format!("reading {:?}", b))?);
+    }
+    Ok(true)
+}
+
+/// Returns a function blanking out the store hashes and versions in a
+/// string, so that findings can be matched across versions of a package
+fn version_blanker() -> impl Fn(&str) -> String {
+    let varying = regex::Regex::new(r"[0-9a-df-np-sv-z]{32}|[0-9]+(?:\.[0-9]+)*")
+        .expect("failed compiling the hash and version regex");
+    move |s| varying.replace_all(s, "*").into_owned()
 }
 
 fn theme() -> Box<dyn dialoguer::theme::Theme> {
@@ -1,8 +1,9 @@
-use anyhow::{anyhow, Context};
-use nixpkgs_check::{checks, Check, State};
+use anyhow::Context;
+use nixpkgs_check::{checks, Check, Commit, Options, State};
 use std::{
     collections::HashSet,
     path::{Path, PathBuf},
+    rc::Rc,
 };
 use structopt::StructOpt;
 
@@ -26,6 +27,25 @@ struct Opt {
     /// The path towards the nixpkgs repository
     #[structopt(long, default_value = ".", parse(from_os_str))]
     repo_path: PathBuf,
+
+    /// Run the binaries in a sandbox, without network access and with
+    /// a throwaway home directory (requires bubblewrap)
+    #[structopt(long)]
+    sandbox_binaries: bool,
+
+    /// Rebuild the to-check version of the packages to check whether
+    /// they are reproducible (uses diffoscope if available)
+    #[structopt(long)]
+    check_reproducibility: bool,
+
+    /// Report builds that became this many times slower or faster
+    #[structopt(long, default_value = "2")]
+    build_time_threshold: f64,
+
+    /// Rerun the failing tests, and those whose result changed, this
+    /// many times to detect flaky ones
+    #[structopt(long, default_value = "0")]
+    rerun_tests: u32,
 }
 
 fn run(opt: Opt) -> anyhow::Result<()> {
@@ -86,15 +106,37 @@ fn run(opt: Opt) -> anyhow::Result<()> {
         None => State::default(),
     };
 
-    let changed_pkgs = autodetect_changed_pkgs(&opt.repo_path, &opt.base_ref, &opt.to_check_ref)
-        .context("auto-detecting which packages were changed based on commit message")?;
+    let options = Rc::new(Options {
+        sandbox_binaries: opt.sandbox_binaries,
+        check_reproducibility: opt.check_reproducibility,
+        build_time_threshold: opt.build_time_threshold,
+        rerun_tests: opt.rerun_tests,
+        run_dir: xdg_dirs.get_data_home().join("runs").join(format!(
+            "{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .context("reading the current time")?
+                .as_secs()
+        )),
+    });
+
+    let commits = Rc::new(
+        pr_commits(&opt.repo_path, &opt.base_ref, &opt.to_check_ref)
+            .context("listing the commits to check")?,
+    );
+    let changed_pkgs = autodetect_changed_pkgs(&commits);
 
     // Note: these three checks all don't have the run_{before,after}
     // methods implemented
     let mut checks = vec![
         Box::new(checks::environment::Chk::new(&killer_r).context("checking the environment")?)
             as Box<dyn Check>,
-        Box::new(checks::ask_pkg_names::Chk::new(changed_pkgs)?),
+        Box::new(checks::ask_pkg_names::Chk::new(
+            changed_pkgs,
+            options.clone(),
+            commits.clone(),
+            &mut state,
+        )?),
         Box::new(checks::ask_other_tests::Chk::new()?),
         Box::new(checks::confirm_contributing::Chk::new(&mut state)?),
     ];
@@ -313,11 +355,9 @@ fn setup_checkout(repo: &git2::Repository, path: &Path, oid: git2::Oid) -> anyho
     Ok(())
 }
 
-fn autodetect_changed_pkgs(
-    repo_path: &Path,
-    base_ref: &str,
-    to_check_ref: &str,
-) -> anyhow::Result<HashSet<String>> {
+/// Lists the commits of to-check that are not in base, from the newest
+/// to the oldest
+fn pr_commits(repo_path: &Path, base_ref: &str, to_check_ref: &str) -> anyhow::Result<Vec<Commit>> {
     // Open the repo
     let repo = git2::Repository::open(repo_path)
         .with_context(|| format!("opening the nixpkgs repo {:?}", repo_path))?;
@@ -338,7 +378,7 @@ fn autodetect_changed_pkgs(
         .merge_base(base_obj.id(), to_check_obj.id())
         .context("finding the merge-base of the base reference and the to-check reference")?;
 
-    let mut pkgs = HashSet::new();
+    let mut commits = Vec::new();
     let mut commit = to_check_obj
         .peel_to_commit()
         .context("peeling to-check object to a commit")?;
@@ -346,20 +386,13 @@ fn autodetect_changed_pkgs(
         if commit.id() == base_oid {
             break;
         }
-        if commit.parent_count() == 1 {
-            // merge commits are usually not commits we're interested in
-            let summary = commit
-                .summary()
-                .ok_or_else(|| anyhow!("commit {} has a non-utf-8 summary", commit.id()))?;
-            let pkg = summary.split(":").next().ok_or_else(|| {
-                anyhow!(
-                    "commit {} has summary that does not respect the convention: {}",
-                    commit.id(),
-                    summary
-                )
-            })?;
-            pkgs.insert(pkg.to_string());
-        }
+        let message = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+        commits.push(Commit {
+            id: commit.id().to_string(),
+            summary: message.lines().next().unwrap_or("").to_string(),
+            message,
+            is_merge: commit.parent_count() > 1,
+        });
         commit = commit
             .parent(0)
             .with_context(|| format!("recovering parent of commit {}", commit.id()))?;
@@ -368,5 +401,15 @@ fn autodetect_changed_pkgs(
         // version
     }
 
-    Ok(pkgs)
+    Ok(commits)
+}
+
+fn autodetect_changed_pkgs(commits: &[Commit]) -> HashSet<String> {
+    commits
+        .iter()
+        // merge commits are usually not commits we're interested in
+        .filter(|c| !c.is_merge)
+        .filter_map(|c| c.summary.split(':').next())
+        .map(|pkg| pkg.to_string())
+        .collect()
 }
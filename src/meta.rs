@@ -0,0 +1,93 @@
+//! Evaluation of the `meta` attribute of packages, for the checks
+//! comparing it between the base and to-check versions
+
+use anyhow::Context;
+use crossbeam_channel::Receiver;
+
+/// The parts of `meta` the checks look at, with unset or failing
+/// attributes mapped to `None` or empty lists
+#[derive(serde::Deserialize)]
+pub struct Meta {
+    pub description: Option<String>,
+    pub homepages: Vec<String>,
+    pub licenses: Vec<License>,
+    pub maintainers: Vec<Maintainer>,
+    pub main_program: Option<String>,
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq)]
+pub struct License {
+    pub spdx_id: Option<String>,
+    pub short_name: Option<String>,
+    pub free: bool,
+    pub redistributable: bool,
+}
+
+impl License {
+    pub fn name(&self) -> &str {
+        self.spdx_id
+            .as_deref()
+            .or(self.short_name.as_deref())
+            .unwrap_or("unknown")
+    }
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq)]
+pub struct Maintainer {
+    pub github: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Maintainer {
+    pub fn name(&self) -> &str {
+        self.github
+            .as_deref()
+            .or(self.name.as_deref())
+            .unwrap_or("unknown")
+    }
+}
+
+/// Evaluates the meta of the package, or returns `None` if interrupted
+pub fn eval(killer: &Receiver<()>, version: &str, pkg: &str) -> anyhow::Result<Option<Meta>> {
+    crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(let \
+                    meta = {}.meta or {{}}; \
+                    get = default: v: \
+                      let r = builtins.tryEval (builtins.deepSeq v v); \
+                      in if r.success then r.value else default; \
+                    toList = v: if v == null then [] else if builtins.isList v then v else [ v ]; \
+                    license = l: if builtins.isAttrs l then {{ \
+                        spdx_id = l.spdxId or null; \
+                        short_name = l.shortName or null; \
+                        free = l.free or true; \
+                        redistributable = l.redistributable or (l.free or true); \
+                      }} else {{ \
+                        spdx_id = null; \
+                        short_name = toString l; \
+                        free = true; \
+                        redistributable = true; \
+                      }}; \
+                    maintainer = m: {{ github = m.github or null; name = m.name or null; }}; \
+                  in {{ \
+                    description = get null (meta.description or null); \
+                    homepages = get [] (toList (meta.homepage or null)); \
+                    licenses = get [] (map license (toList (meta.license or null))); \
+                    maintainers = get [] (map maintainer (meta.maintainers or [])); \
+                    main_program = get null (meta.mainProgram or null); \
+                  }})",
+                crate::nix_eval_for(pkg)
+            ),
+        ],
+    )
+    .with_context(|| format!("evaluating the meta of {} version of {}", version, pkg))?
+    .map(|meta| {
+        serde_json::from_value(meta)
+            .with_context(|| format!("parsing the meta of {} version of {}", version, pkg))
+    })
+    .transpose()
+}
@@ -0,0 +1,208 @@
+//! Retrieval of the upstream sources of packages, for checks comparing
+//! them between the base and to-check versions
+
+use anyhow::{anyhow, Context};
+use crossbeam_channel::Receiver;
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// The version and unpacked `src` of a package
+pub struct Source {
+    pub version: Option<String>,
+    /// The root of the source tree, if the package has a source that
+    /// could be fetched and unpacked
+    pub tree: Option<PathBuf>,
+    _unpacked: Option<tempfile::TempDir>,
+}
+
+/// The sources of a package, fetched once for all the checks that read
+/// them
+pub struct Shared {
+    pkg: String,
+    fetched: RefCell<HashMap<String, Rc<Source>>>,
+}
+
+impl Shared {
+    pub fn new(pkg: String) -> Shared {
+        Shared {
+            pkg,
+            fetched: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the source of the `version` currently checked out, fetching
+    /// it on first use, or `None` if interrupted
+    pub fn get(&self, killer: &Receiver<()>, version: &str) -> anyhow::Result<Option<Rc<Source>>> {
+        if let Some(source) = self.fetched.borrow().get(version) {
+            return Ok(Some(source.clone()));
+        }
+        let source = match fetch(killer, version, &self.pkg)? {
+            Some(source) => Rc::new(source),
+            None => return Ok(None),
+        };
+        self.fetched
+            .borrow_mut()
+            .insert(version.to_string(), source.clone());
+        Ok(Some(source))
+    }
+}
+
+/// Evaluates the version of the package, and realises and unpacks its
+/// `src` like the unpack phase would, or returns `None` if interrupted
+fn fetch(killer: &Receiver<()>, version: &str, pkg: &str) -> anyhow::Result<Option<Source>> {
+    let eval = crate::nix(
+        killer,
+        &[
+            "eval",
+            "--json",
+            &format!(
+                "(let pkg = {}; in {{ \
+                    version = pkg.version or null; \
+                    src = if builtins.isAttrs (pkg.src or null) then pkg.src.outPath else null; \
+                  }})",
+                crate::nix_eval_for(pkg)
+            ),
+        ],
+    )
+    .with_context(|| format!("evaluating the source of {} version of {}", version, pkg))?;
+    let eval = match eval {
+        Some(eval) => eval,
+        None => return Ok(None),
+    };
+    let version_str = eval["version"].as_str().map(|v| v.to_string());
+    let src = match eval["src"].as_str() {
+        Some(src) => PathBuf::from(src),
+        None => {
+            return Ok(Some(Source {
+                version: version_str,
+                tree: None,
+                _unpacked: None,
+            }))
+        }
+    };
+
+    let realised = crate::run_nix(
+        killer,
+        false,
+        &[
+            "build",
+            "--no-link",
+            &crate::nix_eval_for(&format!("{}.src", pkg)),
+        ],
+    )
+    .with_context(|| format!("fetching the source of {} version of {}", version, pkg))?;
+    match realised {
+        None => return Ok(None),
+        Some(out) if !out.status.success() || !src.exists() => {
+            return Ok(Some(Source {
+                version: version_str,
+                tree: None,
+                _unpacked: None,
+            }))
+        }
+        Some(_) => (),
+    }
+
+    if src.is_dir() {
+        return Ok(Some(Source {
+            version: version_str,
+            tree: Some(src),
+            _unpacked: None,
+        }));
+    }
+    let unpacked = tempfile::tempdir().context("creating temporary directory")?;
+    let tree = match unpack(killer, &src, unpacked.path())
+        .with_context(|| format!("unpacking {:?}", src))?
+    {
+        Some(tree) => tree,
+        None => return Ok(None),
+    };
+    Ok(Some(Source {
+        version: version_str,
+        tree,
+        _unpacked: Some(unpacked),
+    }))
+}
+
+/// Unpacks the archive in `dir`, and returns the root of the unpacked
+/// tree, if the file is an archive
+fn unpack(
+    killer: &Receiver<()>,
+    archive: &Path,
+    dir: &Path,
+) -> anyhow::Result<Option<Option<PathBuf>>> {
+    let archive_str = archive
+        .to_str()
+        .ok_or_else(|| anyhow!("got non-utf8 store path {:?}", archive))?;
+    let dir_str = dir
+        .to_str()
+        .ok_or_else(|| anyhow!("got non-utf8 temporary directory {:?}", dir))?;
+    let out = if archive_str.ends_with(".zip") {
+        crate::run(
+            killer,
+            true,
+            Path::new("unzip"),
+            &["-qq", archive_str, "-d", dir_str],
+        )
+    } else {
+        // tar detects the compression by itself
+        crate::run(
+            killer,
+            true,
+            Path::new("tar"),
+            &["-xf", archive_str, "-C", dir_str],
+        )
+    }
+    .with_context(|| format!("extracting {:?}", archive))?;
+    match out {
+        None => return Ok(None),
+        Some(out) if !out.status.success() => return Ok(Some(None)),
+        Some(_) => (),
+    }
+
+    // Like the unpack phase, make the sources writable, which also lets
+    // the temporary directory be removed
+    for f in crate::walk_dir(dir)? {
+        let meta = std::fs::symlink_metadata(&f)
+            .with_context(|| format!("reading metadata of {:?}", f))?;
+        if meta.is_dir() {
+            let mut perms = meta.permissions();
+            perms.set_mode(perms.mode() | 0o700);
+            std::fs::set_permissions(&f, perms)
+                .with_context(|| format!("making {:?} writable", f))?;
+        }
+    }
+
+    // Archives usually contain a single top-level directory
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("listing directory {:?}", dir))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("listing directory {:?}", dir))?;
+    match &entries[..] {
+        [entry] if entry.path().is_dir() => Ok(Some(Some(entry.path()))),
+        _ => Ok(Some(Some(dir.to_path_buf()))),
+    }
+}
+
+/// Lists the files of the tree that are not directories, relative to its
+/// root
+pub fn files(tree: &Path) -> anyhow::Result<BTreeSet<PathBuf>> {
+    let mut res = BTreeSet::new();
+    for f in crate::walk_dir(tree)? {
+        let meta = std::fs::symlink_metadata(&f)
+            .with_context(|| format!("reading metadata of {:?}", f))?;
+        if !meta.is_dir() {
+            res.insert(
+                f.strip_prefix(tree)
+                    .expect("walked file is not in walked directory")
+                    .to_path_buf(),
+            );
+        }
+    }
+    Ok(res)
+}